@@ -3,31 +3,43 @@ use cyw43::Control;
 
 use embassy_rp::peripherals::BOOTSEL;
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
+
+/// Holding BOOTSEL this long reboots into the USB bootloader, a shorter press resets the console
+const USB_BOOT_HOLD: Duration = Duration::from_secs(2);
 
 #[embassy_executor::task]
 pub async fn button_task(control_mutex: &'static Mutex::<NoopRawMutex, Control<'static>>, mut bootsel: BOOTSEL) {
     assert!(embassy_rp::pac::SIO.cpuid().read() == 0, "Need to be on core 0");
 
-    let mut prev_state = false;
+    let mut pressed_at = None;
 
     loop {
         Timer::after(Duration::from_millis(32)).await;
 
         let button_state = bootsel.is_pressed();
 
-        if !prev_state && button_state {
-            defmt::info!("Resetting");
-            // On press, turn LED on
-            let mut control = control_mutex.lock().await;
-            control.gpio_set(0, true).await;
-        }
-        else if prev_state && !button_state {
-            // on release, turn LED off and reset to USB boot
-            let mut control = control_mutex.lock().await;
-            control.gpio_set(0, false).await;
-            embassy_rp::rom_data::reset_to_usb_boot(0, 0);
+        match pressed_at {
+            None if button_state => {
+                // On press, turn LED on
+                let mut control = control_mutex.lock().await;
+                control.gpio_set(0, true).await;
+                pressed_at = Some(Instant::now());
+            }
+            Some(at) if !button_state => {
+                // on release, turn LED off and either reset the console or reset to USB boot
+                let mut control = control_mutex.lock().await;
+                control.gpio_set(0, false).await;
+                pressed_at = None;
+
+                if at.elapsed() < USB_BOOT_HOLD {
+                    crate::si::reset::request();
+                } else {
+                    defmt::info!("Resetting");
+                    embassy_rp::rom_data::reset_to_usb_boot(0, 0);
+                }
+            }
+            _ => {}
         }
-        prev_state = button_state;
     }
 }
@@ -0,0 +1,213 @@
+//! Control connection, on TCP port 4303
+//!
+//! Every message from the host starts with a single command byte:
+//!  0xec count, bytes[count]  - echo bytes back, for testing
+//!  0xc0 port, buttons_hi, buttons_lo, x, y - set controller input for port 0-3
+//!  0xc1 port, connected - plug in (1) or unplug (0) a controller
+//!  0xc2 port, pak - change the accessory in a controller's pak slot, see `PakKind`. There's
+//!                   one Controller Pak, plugging it in takes it out of the other controllers.
+//!  0xb0 cic[2] - set the cartridge's CIC by part number, e.g. 6102, for the next boot
+//!  0xb1 region - set the console region, see `Region`, for the next boot
+//!  0xb2 - press the console's reset button
+//!  0xd0 kind, image[size] - replace the cartridge EEPROM, see `EepromKind` for kinds and sizes
+//!  0xd1 - read back the cartridge EEPROM
+//!  0xd2 enabled - plug in (1) or remove (0) the cartridge RTC
+//!  0xd3 time[8] - set the cartridge RTC, as big endian unix time in seconds
+//!
+//! picopif sends events back to the host:
+//!  0xe0 port, on - a Rumble Pak motor turned on (1) or off (0)
+//!  0xe1 kind, image[size] - the cartridge EEPROM, in reply to 0xd1
+
+use core::cmp::min;
+
+use defmt::*;
+use embassy_net::tcp::TcpSocket;
+use embassy_futures::select::{select, Either};
+use embassy_net::Stack;
+use embassy_time::Duration;
+use embedded_io_async::{Read, ReadExactError, Write};
+
+use crate::si::cic::{self, Cic};
+use crate::si::controller::{self, PakKind};
+use crate::si::eeprom::{self, EepromKind};
+use crate::si::region::{self, Region};
+use crate::si::reset;
+use crate::si::rtc;
+use crate::si::rumble;
+
+const PORT: u16 = 4303;
+
+#[embassy_executor::task]
+pub async fn ctrl_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
+    let mut rx_buffer = [0; 0x200];
+    let mut tx_buffer = [0; 0x20];
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+
+        info!("Listening on port {}...", PORT);
+        if let Err(e) = socket.accept(PORT).await {
+            warn!("accept error: {:?}", e);
+            continue;
+        }
+        info!("Accepted connection");
+
+        handle_ctrl(&mut socket).await.err().map(|e| warn!("ctrl error: {:?}", e));
+
+        // Don't leave buttons held down once the host has gone away
+        controller::release_all();
+
+        socket.write_all(b"goodbye").await.ok();
+        socket.close();
+        socket.flush().await.ok();
+    }
+}
+
+#[derive(defmt::Format)]
+pub enum CtrlError {
+    ConnectionReset,
+    UnknownCommand,
+    InvalidPort,
+    InvalidPak,
+    InvalidEeprom,
+    InvalidCic,
+    InvalidRegion,
+}
+
+impl From<embassy_net::tcp::Error> for CtrlError {
+    fn from(_: embassy_net::tcp::Error) -> Self {
+        CtrlError::ConnectionReset
+    }
+}
+
+impl From<ReadExactError<embassy_net::tcp::Error>> for CtrlError {
+    fn from(_: ReadExactError<embassy_net::tcp::Error>) -> Self {
+        CtrlError::ConnectionReset
+    }
+}
+
+fn port(port: u8) -> Result<usize, CtrlError> {
+    match port as usize {
+        port if port < controller::PORTS => Ok(port),
+        _ => Err(CtrlError::InvalidPort),
+    }
+}
+
+async fn handle_ctrl(socket: &mut TcpSocket<'_>) -> Result<(), CtrlError> {
+    let mut buf = [0u8; 0x20];
+
+    loop {
+        let (mut read, mut write) = socket.split();
+
+        match select(read.read_exact(&mut buf[..1]), rumble::next_event()).await {
+            Either::First(result) => result?,
+            Either::Second(event) => {
+                debug!("{}", event);
+                write.write_all(&[0xe0, event.port, event.on as u8]).await?;
+                continue;
+            }
+        }
+        trace!("cmd {:x}", buf[0]);
+
+        match buf[0] {
+            // echo, for testing.
+            0xec => {
+                read.read(&mut buf[1..2]).await.ok();
+
+                let mut count = buf[1] as usize;
+                info!("echoing {} bytes", count);
+                while count != 0 {
+                    let len = min(count, buf.len());
+                    let bytes = read.read(&mut buf[..len]).await?;
+                    write.write_all(&buf[..bytes]).await.or(Err(CtrlError::ConnectionReset))?;
+                    count -= bytes;
+                }
+            }
+            // controller input
+            0xc0 => {
+                read.read_exact(&mut buf[..5]).await?;
+                let buttons = u16::from_be_bytes([buf[1], buf[2]]);
+                controller::set_input(port(buf[0])?, buttons, buf[3] as i8, buf[4] as i8);
+            }
+            // controller plugged in/out
+            0xc1 => {
+                read.read_exact(&mut buf[..2]).await?;
+                let port = port(buf[0])?;
+                info!("controller {} {}", port + 1, if buf[1] != 0 { "connected" } else { "disconnected" });
+                controller::set_connected(port, buf[1] != 0);
+            }
+            // controller pak slot
+            0xc2 => {
+                read.read_exact(&mut buf[..2]).await?;
+                let port = port(buf[0])?;
+                let pak = PakKind::try_from(buf[1]).or(Err(CtrlError::InvalidPak))?;
+                info!("controller {} pak: {}", port + 1, pak);
+                controller::set_pak(port, pak);
+            }
+            // cic
+            0xb0 => {
+                read.read_exact(&mut buf[..2]).await?;
+                let number = u16::from_be_bytes([buf[0], buf[1]]);
+                let cic = Cic::from_number(number).ok_or(CtrlError::InvalidCic)?;
+                info!("cic set to {}, reset the console to apply", cic);
+                cic::set_cic(cic);
+            }
+            0xb1 => {
+                read.read_exact(&mut buf[..1]).await?;
+                let region = Region::try_from(buf[0]).or(Err(CtrlError::InvalidRegion))?;
+                info!("region set to {}, reset the console to apply", region);
+                region::set_region(region);
+            }
+            0xb2 => {
+                info!("resetting console");
+                reset::request();
+            }
+            // upload eeprom
+            0xd0 => {
+                read.read_exact(&mut buf[..1]).await?;
+                let kind = EepromKind::try_from(buf[0]).or(Err(CtrlError::InvalidEeprom))?;
+                info!("uploading eeprom: {}", kind);
+
+                let mut offset = 0;
+                while offset < kind.size() {
+                    let len = min(kind.size() - offset, buf.len());
+                    read.read_exact(&mut buf[..len]).await?;
+                    eeprom::write_image(offset, &buf[..len]);
+                    offset += len;
+                }
+                eeprom::set_kind(kind);
+            }
+            // download eeprom
+            0xd1 => {
+                let kind = eeprom::kind();
+                write.write_all(&[0xe1, kind as u8]).await?;
+
+                let mut offset = 0;
+                while offset < kind.size() {
+                    let len = min(kind.size() - offset, buf.len());
+                    eeprom::read_image(offset, &mut buf[..len]);
+                    write.write_all(&buf[..len]).await?;
+                    offset += len;
+                }
+            }
+            // rtc present
+            0xd2 => {
+                read.read_exact(&mut buf[..1]).await?;
+                info!("rtc {}", if buf[0] != 0 { "enabled" } else { "disabled" });
+                rtc::set_enabled(buf[0] != 0);
+            }
+            // set rtc
+            0xd3 => {
+                read.read_exact(&mut buf[..8]).await?;
+                let time = u64::from_be_bytes(buf[..8].try_into().unwrap());
+                info!("rtc time set to {}", time);
+                rtc::set_time(time, rtc::Source::Host);
+            }
+            cmd => {
+                error!("unknown cmd {}", cmd);
+                return  Err(CtrlError::UnknownCommand);
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use embassy_rp::flash::{Async, Flash};
+use embassy_futures::select::select;
+use embassy_rp::{peripherals, Peripheral};
+use embassy_time::{Duration, Timer};
+
+use crate::si::{self, eeprom, gb_cart, mempak};
+use crate::si::region::Region;
+use crate::wifi_firmware::open;
+
+const FLASH_SIZE: usize = 2 * 1024 * 1024;
+const ERASE_SIZE: usize = 4096;
+const XIP_BASE: usize = 0x1000_0000;
+
+// Our own data lives in flash just below the cyw43 firmware, which starts at 0x1c1800.
+// Write images here with `make flash-pif-rom`, `make flash-gb-rom` and so on.
+
+static PIF_ROM_START: usize = (2 * 1024 * 1024) - (256 * 1024); // 0x1c0000
+static PIF_ROM_SIZE: usize = 0x7c0;
+// One PIF ROM image per region, in `Region` order: NTSC, PAL, MPAL
+static PIF_ROM_STRIDE: usize = 0x800;
+
+// The Controller Pak's 32 KB image. There's room for one per port, but only one fits in RAM.
+static MEMPAK_START: usize = (2 * 1024 * 1024) - (384 * 1024); // 0x1a0000
+
+// Cartridge EEPROM: its kind, then up to 2 KB of image
+static EEPROM_START: usize = (2 * 1024 * 1024) - (420 * 1024); // 0x197000
+
+// Game Boy cartridge for the Transfer Pak. The ROM is read in place through XIP.
+static GB_SAVE_START: usize = (2 * 1024 * 1024) - (416 * 1024); // 0x198000
+static GB_ROM_START: usize = 512 * 1024; // 0x080000
+static GB_ROM_SIZE: usize = 1024 * 1024;
+
+pub fn open_pif_rom<'a, FLASH, DMA>(p_flash: &'a mut FLASH, p_dma: &'a mut DMA, region: Region) -> impl embedded_io_async::Read + embedded_io_async::Seek + 'a + defmt::Format
+where
+    FLASH: Peripheral,
+    <FLASH as Peripheral>::P: embassy_rp::flash::Instance,
+    DMA: Peripheral + embassy_rp::dma::Channel,
+{
+    open(p_flash, p_dma, PIF_ROM_START + region as usize * PIF_ROM_STRIDE, PIF_ROM_SIZE)
+}
+
+pub fn open_mempak<'a, FLASH, DMA>(p_flash: &'a mut FLASH, p_dma: &'a mut DMA) -> impl embedded_io_async::Read + embedded_io_async::Seek + 'a + defmt::Format
+where
+    FLASH: Peripheral,
+    <FLASH as Peripheral>::P: embassy_rp::flash::Instance,
+    DMA: Peripheral + embassy_rp::dma::Channel,
+{
+    open(p_flash, p_dma, MEMPAK_START, mempak::SIZE)
+}
+
+pub fn open_eeprom<'a, FLASH, DMA>(p_flash: &'a mut FLASH, p_dma: &'a mut DMA) -> impl embedded_io_async::Read + embedded_io_async::Seek + 'a + defmt::Format
+where
+    FLASH: Peripheral,
+    <FLASH as Peripheral>::P: embassy_rp::flash::Instance,
+    DMA: Peripheral + embassy_rp::dma::Channel,
+{
+    open(p_flash, p_dma, EEPROM_START, eeprom::STORE_SIZE)
+}
+
+pub fn open_gb_save<'a, FLASH, DMA>(p_flash: &'a mut FLASH, p_dma: &'a mut DMA) -> impl embedded_io_async::Read + embedded_io_async::Seek + 'a + defmt::Format
+where
+    FLASH: Peripheral,
+    <FLASH as Peripheral>::P: embassy_rp::flash::Instance,
+    DMA: Peripheral + embassy_rp::dma::Channel,
+{
+    open(p_flash, p_dma, GB_SAVE_START, gb_cart::SAVE_SIZE)
+}
+
+/// The Game Boy ROM region, memory mapped. Requires XIP to be enabled.
+pub fn gb_rom() -> &'static [u8] {
+    unsafe { core::slice::from_raw_parts((XIP_BASE + GB_ROM_START) as *const u8, GB_ROM_SIZE) }
+}
+
+/// Collects the sectors written to a save image, until they can be written to flash
+struct PendingSave {
+    start: usize,
+    sector_size: usize,
+    sectors: u8,
+}
+
+impl PendingSave {
+    const fn new(start: usize, sector_size: usize) -> Self {
+        Self { start, sector_size, sectors: 0 }
+    }
+
+    /// Write out every sector written since the last save. Returns whether they all were,
+    /// as a console being switched on stops the save, leaving the rest for next time.
+    fn save<'a>(&mut self, flash: &mut Flash<'_, peripherals::FLASH, Async, FLASH_SIZE>, dirty: u8, sector: impl Fn(usize) -> &'a [u8]) -> bool {
+        self.sectors |= dirty;
+        if self.sectors == 0 {
+            return false;
+        }
+
+        for i in 0..8 {
+            if self.sectors & (1 << i) == 0 {
+                continue;
+            }
+            if si::powered() {
+                return false;
+            }
+            let offset = (self.start + i * self.sector_size) as u32;
+            let result = flash
+                .blocking_erase(offset, offset + ERASE_SIZE as u32)
+                .and_then(|()| flash.blocking_write(offset, sector(i)));
+            if let Err(e) = result {
+                defmt::error!("Failed to save sector at {:06x}: {:?}", offset, e);
+            }
+            self.sectors &= !(1 << i);
+        }
+        true
+    }
+}
+
+/// Write modified save data back to flash, but only while the console is off.
+///
+/// Flash erase and program run with interrupts disabled and core 1 paused, as nothing can
+/// execute from flash meanwhile. A sector erase can take ~50 ms, far longer than the game
+/// will wait for an SI response, so core 1 must only be paused while there's no console to
+/// answer. Saves made during play are written out as soon as the console is switched off,
+/// and changes from the network while it's off within a second. A save stops between
+/// sectors if the console is switched back on, so at worst the sector being erased holds up
+/// the start of a boot. Pulling picopif's power with the console still on loses anything
+/// saved since it was switched on.
+#[embassy_executor::task]
+pub async fn save_task(p_flash: peripherals::FLASH, p_dma: peripherals::DMA_CH1) -> ! {
+    let mut flash = Flash::<_, Async, FLASH_SIZE>::new(p_flash, p_dma);
+
+    let mut mempak = PendingSave::new(MEMPAK_START, mempak::SECTOR_SIZE);
+    let mut eeprom = PendingSave::new(EEPROM_START, ERASE_SIZE);
+    let mut gb_save = PendingSave::new(GB_SAVE_START, gb_cart::SECTOR_SIZE);
+
+    loop {
+        select(si::powered_off(), Timer::after(Duration::from_secs(1))).await;
+        if si::powered() {
+            continue;
+        }
+
+        if mempak.save(&mut flash, mempak::take_dirty(), mempak::sector) {
+            defmt::info!("Saved mempak");
+        }
+
+        if eeprom.save(&mut flash, eeprom::take_dirty() as u8, |_| eeprom::store()) {
+            defmt::info!("Saved EEPROM");
+        }
+
+        if gb_save.save(&mut flash, gb_cart::take_dirty(), gb_cart::sector) {
+            defmt::info!("Saved Game Boy save RAM");
+        }
+    }
+}
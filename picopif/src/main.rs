@@ -6,11 +6,16 @@
 #![feature(impl_trait_in_fn_trait_return)]
 
 mod button;
+#[cfg(feature = "wifi")]
+mod ctrl;
+mod flash_store;
 mod si;
+#[cfg(feature = "wifi")]
+mod sntp;
+#[cfg(feature = "wifi")]
+mod trace_stream;
 mod wifi_firmware;
 
-use core::cmp::min;
-
 use defmt::*;
 #[cfg(feature = "rtt-log")]
 use panic_probe as _;
@@ -19,23 +24,24 @@ use defmt_rtt as _;
 
 use cyw43::Control;
 use cyw43_pio::PioSpi;
-use embassy_executor::Spawner;
-use embassy_net::tcp::TcpSocket;
+use embassy_executor::{Executor, Spawner};
 #[cfg(feature = "wifi")]
 use embassy_net::{Config, Stack, StackResources};
 use embassy_rp::bind_interrupts;
 use embassy_rp::gpio::{Level, Output};
+use embassy_rp::multicore::{self, spawn_core1};
 use embassy_rp::peripherals::*;
 use embassy_rp::pio::{InterruptHandler as PioInterruptHandler, Pio};
 #[cfg(feature = "usb_log")]
 use embassy_rp::usb::{Driver, InterruptHandler as UsbInterruptHandler};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Duration, Instant, Timer};
+use embassy_time::Instant;
 
-use embedded_io_async::Write;
+use static_cell::{make_static, StaticCell};
 
-use static_cell::make_static;
+static mut CORE1_STACK: multicore::Stack<8192> = multicore::Stack::new();
+static EXECUTOR1: StaticCell<Executor> = StaticCell::new();
 
 #[cfg(feature = "wifi")]
 const WIFI_NETWORK: &str = env!("WIFI_NETWORK");
@@ -45,7 +51,8 @@ const WIFI_PASSWORD: &str = env!("WIFI_PASSWORD");
 #[cfg(feature = "usb_log")]
 bind_interrupts!(struct Irqs {
     PIO0_IRQ_0 => PioInterruptHandler<PIO0>;
-    PIO1_IRQ_0 => PioInterruptHandler<PIO1>;
+    PIO1_IRQ_0 => si::SiInterruptHandler<PIO1>;
+    DMA_IRQ_1 => si::SiDmaInterruptHandler;
     USBCTRL_IRQ => UsbInterruptHandler<USB>;
 });
 
@@ -53,6 +60,7 @@ bind_interrupts!(struct Irqs {
 bind_interrupts!(struct Irqs {
     PIO0_IRQ_0 => PioInterruptHandler<PIO0>;
     PIO1_IRQ_0 => si::SiInterruptHandler<PIO1>;
+    DMA_IRQ_1 => si::SiDmaInterruptHandler;
 });
 
 #[embassy_executor::task]
@@ -72,12 +80,23 @@ async fn logger_task(driver: Driver<'static, USB>) {
     embassy_usb_logger::run!(1024, log::LevelFilter::Info, driver);
 }
 
-#[cfg(feature = "net-log")]
+#[cfg(feature = "wifi")]
 #[embassy_executor::task]
 async fn net_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
     stack.run().await
 }
 
+#[embassy_executor::task]
+async fn event_log_task() -> ! {
+    si::events::log_forever().await
+}
+
+#[cfg(not(feature = "wifi"))]
+#[embassy_executor::task]
+async fn trace_log_task() -> ! {
+    si::trace::log_forever().await
+}
+
 #[cfg(feature = "net-log")]
 #[embassy_executor::task]
 async fn log_drain_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
@@ -114,9 +133,79 @@ async fn main(spawner: Spawner) {
         info!("Starting usb logger");
         let driver = Driver::new(p.USB, Irqs);
         spawner.spawn(logger_task(driver)).unwrap();
-        embassy_time::Timer::after(Duration::from_secs(2)).await;
+        embassy_time::Timer::after(embassy_time::Duration::from_secs(2)).await;
+    }
+
+    if let Some(number) = option_env!("PICOPIF_CIC") {
+        match number.parse().ok().and_then(si::cic::Cic::from_number) {
+            Some(cic) => si::cic::set_cic(cic),
+            None => error!("Unknown CIC {}", number),
+        }
+    }
+    if option_env!("PICOPIF_ROM_READABLE").is_some() {
+        si::set_rom_readable(true);
     }
 
+    if let Some(name) = option_env!("PICOPIF_REGION") {
+        match si::region::Region::from_name(name) {
+            Some(region) => si::region::set_region(region),
+            None => error!("Unknown region {}", name),
+        }
+    }
+
+    for region in si::region::Region::ALL {
+        let mut rom = flash_store::open_pif_rom(&mut p.FLASH, &mut p.DMA_CH1, region);
+        if si::load_rom(region, &mut rom).await.is_err() {
+            error!("Failed to load {} PIF ROM from {}", region, rom);
+        }
+    }
+
+    {
+        let mut mempak = flash_store::open_mempak(&mut p.FLASH, &mut p.DMA_CH1);
+        if si::load_mempak(&mut mempak).await.is_err() {
+            error!("Failed to load mempak from {}", mempak);
+        }
+    }
+
+    {
+        let mut eeprom = flash_store::open_eeprom(&mut p.FLASH, &mut p.DMA_CH1);
+        if si::load_eeprom(&mut eeprom).await.is_err() {
+            error!("Failed to load EEPROM from {}", eeprom);
+        }
+    }
+
+    {
+        let mut save = flash_store::open_gb_save(&mut p.FLASH, &mut p.DMA_CH1);
+        if si::load_gb_cart(flash_store::gb_rom(), &mut save).await.is_err() {
+            error!("Failed to load Game Boy save from {}", save);
+        }
+    }
+
+    // A real PIF has to answer the RCP within milliseconds of power on, so SI comes up before
+    // anything that can be slow or fail, like the network. It gets core 1 to itself, so
+    // nothing on core 0 can hold up a response. Its interrupts are enabled from core 1, so
+    // they are taken there.
+    let mode = match option_env!("PICOPIF_MODE").map(si::Mode::from_name) {
+        None => si::Mode::Respond,
+        Some(Some(mode)) => mode,
+        Some(None) => {
+            error!("Unknown mode {}", option_env!("PICOPIF_MODE"));
+            si::Mode::Respond
+        }
+    };
+    info!("SI mode: {}", mode);
+
+    let (dma, pio1) = (p.DMA_CH3, p.PIO1);
+    let pins = si::Pins { clk: p.PIN_20, pif_in: p.PIN_18, pif_out: p.PIN_19, nmi: p.PIN_21, int2: p.PIN_22, reset_button: p.PIN_17 };
+    spawn_core1(p.CORE1, unsafe { &mut CORE1_STACK }, move || {
+        let executor1 = EXECUTOR1.init(Executor::new());
+        executor1.run(|spawner| match mode {
+            si::Mode::Respond => spawner.spawn(si::si_task(dma, pio1, pins)).unwrap(),
+            si::Mode::Listen => spawner.spawn(si::listen::listen_task(dma, pio1, pins.clk, pins.pif_in, pins.pif_out)).unwrap(),
+        })
+    });
+    spawner.spawn(event_log_task()).unwrap();
+
     let wifi_init = Instant::now();
     let control_mutex: &'static Mutex<NoopRawMutex, Control<'static>>;
     #[cfg(feature = "wifi")]
@@ -157,6 +246,9 @@ async fn main(spawner: Spawner) {
             .await;
     }
 
+    // cyw43 is done with its firmware, the flash is ours from here on
+    spawner.spawn(flash_store::save_task(p.FLASH, p.DMA_CH1)).unwrap();
+
     let wifi_init_time = wifi_init.elapsed();
 
     info!("Booted at {} ms, {}", boot.as_millis(), boot.as_ticks() & 0xffff);
@@ -173,8 +265,6 @@ async fn main(spawner: Spawner) {
         // Doesn't need to be cryptographically secure, this seems to give at least a few bits of entropy.
         let seed = wifi_init_time.as_ticks() & 0xffffffff;
 
-        use static_cell::StaticCell;
-
         static STACK_CELL: StaticCell<Stack<cyw43::NetDriver<'static>>> = StaticCell::new();
         let stack = STACK_CELL.init(Stack::new(
             net_device,
@@ -202,90 +292,15 @@ async fn main(spawner: Spawner) {
         let wifi_join_time = wifi_join.elapsed();
         info!("Connected in {} ms", wifi_join_time.as_millis());
 
-        #[cfg(feature = "net-log")]
-        {
-            spawner.spawn(log_drain_task(stack)).unwrap();
-
-            info!("Waiting for logs to drain");
-            loop {
-                Timer::after(Duration::from_millis(100)).await;
-                if net_logger::is_drained() {
-                    break;
-                }
-            }
-
-            info!("Logs drained, continuing");
-        }
-    }
+        spawner.spawn(ctrl::ctrl_task(stack)).unwrap();
+        spawner.spawn(sntp::sntp_task(stack)).unwrap();
+        spawner.spawn(trace_stream::trace_task(stack, mode)).unwrap();
 
-    si::sniffer(p.DMA_CH3, p.PIO1, p.PIN_20, p.PIN_18, p.PIN_19, p.PIN_21, p.PIN_22).await;
-
-    loop {
-        Timer::after(Duration::from_millis(1000)).await;
+        #[cfg(feature = "net-log")]
+        spawner.spawn(log_drain_task(stack)).unwrap();
     }
 
-    // let mut rx_buffer = [0; 0x200];
-    // let mut tx_buffer = [0; 0x20];
-
-    // loop {
-    //     let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
-    //     socket.set_timeout(Some(Duration::from_secs(10)));
-
-    //     info!("Listening on port 4303...");
-    //     if let Err(e) = socket.accept(4303).await {
-    //         warn!("accept error: {:?}", e);
-    //         continue;
-    //     }
-    //     info!("Accepted connection");
-
-    //     handle_ctrl(&mut socket).await.err().map(|e| warn!("ctrl error: {:?}", e));
-
-    //     socket.write_all(b"goodbye").await.ok();
-    //     socket.close();
-    //     socket.flush().await.ok();
-    // }
-}
-
-#[derive(defmt::Format)]
-pub enum CtrlError {
-    ConnectionReset,
-    UnknownCommand,
-}
-
-impl From<embassy_net::tcp::Error> for CtrlError {
-    fn from(_: embassy_net::tcp::Error) -> Self {
-        CtrlError::ConnectionReset
-    }
+    #[cfg(not(feature = "wifi"))]
+    spawner.spawn(trace_log_task()).unwrap();
 }
 
-async fn handle_ctrl(socket: &mut TcpSocket<'_>) -> Result<(), CtrlError> {
-    //socket.write_all("hello there\n").await?;
-    let mut buf = [0u8; 0x20];
-
-    loop {
-        let (mut read, mut write) = socket.split();
-        read.read(&mut buf[..1]).await.ok();
-        info!("cmd {:x}", buf[0]);
-
-        match buf[0] {
-            // echo, for testing.
-            0xec => {
-                read.read(&mut buf[1..2]).await.ok();
-
-                let mut count = buf[1] as usize;
-                info!("echoing {} bytes", count);
-                while count != 0 {
-                    let len = min(count, buf.len());
-                    let bytes = read.read(&mut buf[..len]).await?;
-                    write.write_all(&buf[..bytes]).await.or(Err(CtrlError::ConnectionReset))?;
-                    count -= bytes;
-                }
-            }
-            cmd => {
-                error!("unknown cmd {}", cmd);
-                return  Err(CtrlError::UnknownCommand);
-            }
-        }
-
-    }
-}
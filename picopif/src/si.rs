@@ -1,16 +1,32 @@
 
 
 use core::marker::PhantomData;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use defmt::println;
+use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_time::{Instant, Duration, Timer};
-use pio::{InstructionOperands, InSource};
+use pio::{InstructionOperands, InSource, WaitSource};
 use pio_proc::pio_file;
 
-use embassy_rp::{pio::{Pio, Config, ShiftDirection, Direction, Instance}, peripherals::*, gpio::{SlewRate, Pull, Input, self, Level, Output, Flex}, pio_instr_util, Peripheral, dma::Channel, pac, interrupt::typelevel::{Handler, Binding}};
+use embassy_rp::{pio::{Pio, Config, ShiftDirection, Direction, Instance}, peripherals::*, gpio::{SlewRate, Pull, Input, self, Level, Output, Flex}, pio_instr_util, Peripheral, dma::Channel, pac, interrupt::{self, typelevel::{Handler, Binding, Interrupt}}};
+use embassy_rp::pac::dma::vals::{DataSize, TreqSel};
 use fixed::FixedU32;
 
 use embassy_rp::RegExt;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use pif_core::{Pif, Request, Response};
+pub use pif_core::{cic, controller, eeprom, gb_cart, mempak, region, rtc, SiCommand};
+
+pub mod events;
+pub mod listen;
+pub mod reset;
+pub mod rumble;
+pub mod trace;
+
+use region::Region;
+use trace::{Record, Transaction};
 
 #[inline(always)]
 fn clocks(pio: &mut Pio<PIO1>) -> u32 {
@@ -18,52 +34,159 @@ fn clocks(pio: &mut Pio<PIO1>) -> u32 {
     u32::MAX - x
 }
 
-#[derive(Copy, Clone)]
-struct LogEntry {
-    cmd: u32,
-    wait_count: u16,
-    diff: i32,
+struct Si {
+    cmd_buf: [u32; 2],
+    pif: Pif,
+    /// DMA channel for 64 byte transfers, and the buffer it transfers from or into
+    dma: u8,
+    dma_buf: [u32; 16],
+    /// A Write64's data is still on its way into `dma_buf`, to be traced as this
+    write64_pending: Option<Transaction>,
+    /// Requests seen since power on
+    requests: u32,
 }
 
-static mut SI_LOG: [LogEntry; 100] = [LogEntry { cmd: 0, wait_count: 0, diff: 0 }; 100];
-static mut COUNT: usize = 0;
+/// Seconds since picopif started, for the cartridge RTC
+fn uptime() -> u64 {
+    Instant::now().as_secs()
+}
+
+/// What picopif does on the SI bus
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Mode {
+    /// Stand in for the PIF
+    Respond = 0,
+    /// Watch a real PIF, see `listen`
+    Listen = 1,
+}
 
-impl defmt::Format for LogEntry {
-    fn format(&self, fmt: defmt::Formatter) {
-        let cmd = SiCommand::from((self.cmd as u32 >> 10) & 0x3);
-        let addr = (self.cmd & 0x3fe) << 1;
-        defmt::write!(fmt, "RCP {} {:03x} {:012b} @ +{} ({} cycle wait)", cmd, addr, self.cmd, self.diff, self.wait_count);
+impl Mode {
+    /// Parse a mode name, as given in `PICOPIF_MODE`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "respond" => Some(Mode::Respond),
+            "listen" | "sniff" => Some(Mode::Listen),
+            _ => None,
+        }
     }
 }
 
-const INST : u32 = (0x02 << 26 | ((0xbfc0_0140u32) >> 2) & 0x03ff_ffff);
-
-const INSTS : &[u32] = &[
-    0x3C093440, // 0
-    0x40896000, // 4
-    0x3C090006, // 8
-    0x3529E463, // c
-    0x40898000, // 14
-    0x3C08A404, // 18
-    0x3C08A404, // 20
-    0x3C01BFC0, // 24 at = 0xbfc00000
-    0x3C02dead, // 28 v0 = 0xdead0000
-    0x3442beef, // 2c v0 = 0xdeadbeef
-    0xac2207cc, // 2c sw v0, 0x7cc(at)
-    0x3C093440, // 2c
-    0x3C093440, // 30
-    0x3C093440, // 34
-    0x3C093440, // 38
-];
+static POWERED: AtomicBool = AtomicBool::new(false);
+static POWERED_OFF: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 
-struct Si {
-    cmd_buf: [u32; 2],
+/// Whether a console is switched on and being served
+pub fn powered() -> bool {
+    POWERED.load(Ordering::Relaxed)
+}
+
+/// Wait for the console to be switched off, which is when save data can go to flash
+pub async fn powered_off() {
+    POWERED_OFF.wait().await
+}
+
+/// Keep the PIF ROM readable after lockout, for debugging. Must be called before the SI
+/// interrupt is enabled.
+pub fn set_rom_readable(readable: bool) {
+    unsafe { SI_INSTANCE.pif.set_rom_readable(readable) };
 }
 
 static mut SI_INSTANCE : Si = Si {
-    cmd_buf: [(32 << 16) | 11, 0u32]
+    cmd_buf: [(32 << 16) | 11, 0u32],
+    pif: Pif::new(uptime),
+    dma: 0,
+    dma_buf: [0; 16],
+    write64_pending: None,
+    requests: 0,
 };
 
+/// Copy a region's PIF ROM image into RAM. Must be called before the SI interrupt is enabled.
+pub async fn load_rom<F>(region: Region, file: &mut F) -> Result<(), embedded_io_async::ReadExactError<F::Error>>
+where
+    F: embedded_io_async::Read,
+{
+    let rom = unsafe { SI_INSTANCE.pif.rom_mut(region) };
+    file.read_exact(rom.bytes_mut()).await?;
+
+    if rom.is_blank() && region == region::region() {
+        defmt::warn!("{} PIF ROM region is blank, flash an image with `make flash-pif-rom`", region);
+    }
+    Ok(())
+}
+
+/// Copy the Controller Pak image into RAM. Must be called before the SI interrupt is enabled.
+pub async fn load_mempak<F>(file: &mut F) -> Result<(), embedded_io_async::ReadExactError<F::Error>>
+where
+    F: embedded_io_async::Read,
+{
+    file.read_exact(unsafe { mempak::image_mut() }).await
+}
+
+/// Copy the cartridge EEPROM into RAM. Must be called before the SI interrupt is enabled.
+pub async fn load_eeprom<F>(file: &mut F) -> Result<(), embedded_io_async::ReadExactError<F::Error>>
+where
+    F: embedded_io_async::Read,
+{
+    file.read_exact(unsafe { eeprom::store_mut() }).await?;
+    defmt::info!("EEPROM: {}", eeprom::kind());
+    Ok(())
+}
+
+/// Insert a Game Boy cartridge into the Transfer Paks and load its save RAM.
+/// Must be called before the SI interrupt is enabled.
+pub async fn load_gb_cart<F>(rom: &'static [u8], save: &mut F) -> Result<(), embedded_io_async::ReadExactError<F::Error>>
+where
+    F: embedded_io_async::Read,
+{
+    unsafe {
+        SI_INSTANCE.pif.insert_gb_cart(rom);
+        save.read_exact(gb_cart::save_ram_mut()).await
+    }
+}
+
+/// Start moving 16 words between `dma_buf` and a PIO FIFO, without waiting for it to finish
+#[inline(always)]
+unsafe fn start_dma(ch: u8, read: *const u32, write: *mut u32, treq: u8, incr_read: bool, incr_write: bool, quiet: bool) {
+    let regs = pac::DMA.ch(ch as usize);
+    regs.read_addr().write_value(read as u32);
+    regs.write_addr().write_value(write as u32);
+    regs.trans_count().write_value(16);
+    regs.ctrl_trig().write(|w| {
+        w.set_treq_sel(TreqSel(treq));
+        w.set_data_size(DataSize::SIZE_WORD);
+        w.set_incr_read(incr_read);
+        w.set_incr_write(incr_write);
+        w.set_chain_to(ch);
+        w.set_irq_quiet(quiet);
+        w.set_en(true);
+    });
+}
+
+/// Finishes a Write64 once its DMA transfer is done. Bound to DMA_IRQ_1, which embassy doesn't use.
+pub struct SiDmaInterruptHandler;
+
+impl Handler<interrupt::typelevel::DMA_IRQ_1> for SiDmaInterruptHandler {
+    unsafe fn on_interrupt() {
+        let si = &mut SI_INSTANCE;
+        pac::DMA.ints1().write_value(1 << si.dma);
+
+        if let Some(mut transaction) = si.write64_pending.take() {
+            transaction.len = 16;
+            transaction.data = si.dma_buf;
+            trace::record(Record::Transaction(transaction));
+            si.pif.handle(Request::Write64(si.dma_buf));
+        }
+    }
+}
+
+/// Forced onto `process` while it's stalled pulling its next command, so that it waits for
+/// PIF_IN to go high first. The stalled `out` carries on once this finishes.
+const WAIT_PIF_IN_HIGH: u16 = InstructionOperands::WAIT {
+    polarity: 1,
+    source: WaitSource::PIN,
+    index: 0,
+    relative: false,
+}.encode();
+
 pub struct SiInterruptHandler<PIO> {
     _pio: PhantomData<PIO>,
 }
@@ -82,14 +205,17 @@ impl<PIO: Instance> Handler<PIO::Interrupt> for SiInterruptHandler<PIO> {
 
         let ints = pio.irqs(0).ints().read();
         let si = &mut SI_INSTANCE;
-        let count = unsafe { COUNT };
 
         if !ints.sm0() {
-            defmt::warn!("Unexpected interrupt {:x}", ints.0);
             return;
         }
         pio.irq().write(|irq| irq.set_irq(1));
 
+        // The start bit of a Write64's data raises the interrupt too, but DMA is handling that
+        if si.write64_pending.is_some() {
+            return;
+        }
+
         let mut wait_count = 0u16;
 
         // Wait for data to be ready
@@ -98,48 +224,45 @@ impl<PIO: Instance> Handler<PIO::Interrupt> for SiInterruptHandler<PIO> {
         // read data
         let packet = pio.rxf(0).read();
 
-        unsafe {
-            if COUNT < SI_LOG.len() {
-                SI_LOG[COUNT] = LogEntry { cmd: packet, wait_count, diff: clk as i32 };
-            }
-            COUNT += 1;
-        }
+        si.requests += 1;
+        let mut transaction = Transaction::new(clk, packet, wait_count);
 
         if packet == 0 {
-            // this is probably a reset command
-            for i in 0..10000 {
-                if pac::IO_BANK0.gpio(18).status().read().infrompad() {
-                    pio.txf(0).write_value((32 << 1) | (11 << 16) );
-                    pio.txf(0).write_value( 0 );
-                    println!("Reset after {} loops", i);
-                    return;
-                }
-            }
-            println!("Reset not detected");
+            // The RCP holds PIF_IN low while it's in reset, so this is all we'll get. Have the
+            // state machine wait for PIF_IN to come back up before it answers and listens for
+            // the next command, rather than waiting for it here.
+            trace::record(Record::Transaction(transaction));
+            pio.sm(0).instr().write(|instr| instr.set_instr(WAIT_PIF_IN_HIGH));
+            pio.txf(0).write_value((32 << 1) | (11 << 16) );
+            pio.txf(0).write_value( 0 );
             return;
         }
 
-        let cmd = SiCommand::from((packet >> 10) & 0x3);
-        let addr = (packet >> 1) as usize & 0x1ff;
+        let cmd = SiCommand::from_packet(packet);
+        let addr = pif_core::word_addr(packet);
 
         match cmd {
             SiCommand::Write64 => {
-                // Untested
-                let mut data = [0u32; 16];
                 pio.txf(0).write_value( (511 << 16) | 0 );
                 pio.txf(0).write_value(1 | (11 << 16) );
-                for i in 0..16 {
-                    while (pio.fstat().read().rxempty() & 1) == 1 { };
-                    data[i] = pio.rxf(0).read();
-                }
-                defmt::info!("Write64 {:03x} {:08x}", addr, data);
+
+                // SiDmaInterruptHandler takes over once all 16 words are in
+                si.write64_pending = Some(transaction);
+                let treq = PIO::PIO_NO * 8 + 4;
+                start_dma(si.dma, pio.rxf(0).as_ptr() as *const u32, si.dma_buf.as_mut_ptr(), treq, false, true, false);
             }
             SiCommand::Read64 => {
-                //defmt::error!("Read64 not implemented");
-                pio.txf(0).write_value((512 << 1) | (10 << 16) );
-                for i in 0..16 {
-                    pio.txf(0).write_value( addr as u32 + i);
+                if let Response::Read64(data) = si.pif.handle(Request::Read64) {
+                    si.dma_buf = data;
                 }
+
+                pio.txf(0).write_value((512 << 1) | (10 << 16) );
+                let treq = PIO::PIO_NO * 8;
+                start_dma(si.dma, si.dma_buf.as_ptr(), pio.txf(0).as_ptr(), treq, true, false, true);
+
+                transaction.len = 16;
+                transaction.data = si.dma_buf;
+                trace::record(Record::Transaction(transaction));
             }
             SiCommand::Write4 => {
                 pio.txf(0).write_value( (31 << 16) | 0 );
@@ -148,19 +271,24 @@ impl<PIO: Instance> Handler<PIO::Interrupt> for SiInterruptHandler<PIO> {
                 while (pio.fstat().read().rxempty() & 1) == 1 { };
                 let data = pio.rxf(0).read();
 
-                //println!("Write4 {:03x} {:08x}", addr, data);
+                transaction.len = 1;
+                transaction.data[0] = data;
+                trace::record(Record::Transaction(transaction));
+
+                si.pif.handle(Request::Write4(addr, data));
             },
             SiCommand::Read4 => {
-                let inst = if addr < INSTS.len() {
-                    INSTS[addr]
-                } else {
-                    INST
+                let inst = match si.pif.handle(Request::Read4(addr)) {
+                    Response::Read4(inst) => inst,
+                    _ => 0,
                 };
 
-                println!("Read4 {:03x} {:08x}", addr << 2, inst);
-
                 pio.txf(0).write_value((32 << 1) | (11 << 16) );
                 pio.txf(0).write_value( inst );
+
+                transaction.len = 1;
+                transaction.data[0] = inst;
+                trace::record(Record::Transaction(transaction));
             }
         }
 
@@ -172,18 +300,60 @@ impl<PIO: Instance> Handler<PIO::Interrupt> for SiInterruptHandler<PIO> {
 struct FakeIrqs;
 unsafe impl<PIO: Instance> Binding<PIO::Interrupt, embassy_rp::pio::InterruptHandler<PIO>> for FakeIrqs {}
 
-pub async fn sniffer<DMA>(dma: impl Peripheral<P = DMA>, pio_periph: PIO1, pif_clk: PIN_20, pif_in: PIN_18, pif_out: PIN_19, nmi: PIN_21, int2: PIN_22) where DMA: Channel {
+/// Press reset: pre-NMI, then NMI once the game has had time to settle
+async fn reset(nmi: &mut Flex<'_, PIN_21>, int2: &mut Flex<'_, PIN_22>) {
+    defmt::info!("Reset: pre-NMI");
+    int2.set_low();
+    Timer::after(reset::PRE_NMI).await;
+
+    // IPL1 runs again after NMI, and expects the PIF to be ready for it
+    critical_section::with(|_| unsafe { SI_INSTANCE.pif.boot(region::region(), cic::cic(), true) });
+
+    defmt::info!("Reset: NMI");
+    nmi.set_low();
+    Timer::after(reset::NMI_PULSE).await;
+    nmi.set_high();
+    int2.set_high();
+}
+
+/// Whether the reset button stays as it is for `reset::DEBOUNCE`, rather than bouncing
+async fn steady(button: &mut Input<'_, PIN_17>) -> bool {
+    matches!(select(button.wait_for_any_edge(), Timer::after(reset::DEBOUNCE)).await, Either::Second(()))
+}
+
+/// The pins wired to the PIF socket, and the console's reset button
+pub struct Pins {
+    pub clk: PIN_20,
+    pub pif_in: PIN_18,
+    pub pif_out: PIN_19,
+    pub nmi: PIN_21,
+    pub int2: PIN_22,
+    pub reset_button: PIN_17,
+}
+
+#[embassy_executor::task]
+pub async fn si_task(dma: DMA_CH3, pio: PIO1, pins: Pins) -> ! {
+    sniffer(dma, pio, pins).await
+}
+
+/// Serve the PIF for every boot of the console, re-arming after each power cycle
+pub async fn sniffer<DMA>(dma: impl Peripheral<P = DMA>, pio_periph: PIO1, pins: Pins) -> ! where DMA: Channel {
+    let Pins { clk: pif_clk, pif_in, pif_out, nmi, int2, reset_button } = pins;
     let mut pio = Pio::new(pio_periph, FakeIrqs);
 
+    // 64 byte transfers go through DMA, with completion signalled on DMA_IRQ_1
+    let dma = dma.into_ref();
+    unsafe { SI_INSTANCE.dma = dma.number() };
+    pac::DMA.inte1().write_set(|m| *m |= 1 << dma.number());
+    interrupt::typelevel::DMA_IRQ_1::unpend();
+    unsafe { interrupt::typelevel::DMA_IRQ_1::enable() };
+
     let mut nmi = Flex::new(nmi);
     let mut int2 = Flex::new(int2);
+    // The console's reset button, pulling low when pressed
+    let mut reset_button = Input::new(reset_button, Pull::Up);
     let mut gpio_pif_in = Input::new(unsafe { pif_in.clone_unchecked() }, Pull::Down);
 
-    #[cfg(feature = "net-log")]
-    while !net_logger::is_drained() {
-        embassy_futures::yield_now().await;
-    }
-
     #[cfg(feature = "rtt-log")] {
 
     }
@@ -213,122 +383,134 @@ pub async fn sniffer<DMA>(dma: impl Peripheral<P = DMA>, pio_periph: PIO1, pif_c
     let process = pio.common.load_program(&process.program);
     let loaded_counter = pio.common.load_program(&counter.program);
 
-    let mut cfg_counter = Config::default();
-    cfg_counter.use_program(&loaded_counter, &[]);
-    cfg_counter.shift_in.auto_fill = true;
-    cfg_counter.shift_out.auto_fill = true;
-    cfg_counter.clock_divider = FixedU32::ONE;
-
-    pio.sm1.set_config(&cfg_counter);
-    unsafe {
-        pio_instr_util::set_x(&mut pio.sm1, u32::MAX);
-    }
-    pio.sm1.set_enable(true);
-
-    let mut cfg_process = Config::default();
-    cfg_process.use_program(&process, &[]);
-    cfg_process.set_in_pins(&[&pif_in]);
-    cfg_process.set_set_pins(&[&pif_out]);
-    cfg_process.set_out_pins(&[&pif_out]);
-    cfg_process.out_sticky = true;
-    cfg_process.shift_in.direction = ShiftDirection::Left;
-    cfg_process.shift_in.auto_fill = true;
-    cfg_process.shift_out.auto_fill = true;
-    cfg_process.shift_out.direction = ShiftDirection::Left;
-    cfg_process.clock_divider = FixedU32::ONE;
-
-    pio.sm0.set_config(&cfg_process);
-    pio.sm0.set_enable(true);
-
-
-    defmt::println!("Ready. INST is {:08x}", INST);
-
-    gpio_pif_in.wait_for_high().await;
-    let ready_clks = clocks(&mut pio);
-    let rcp_up = Instant::now();
-
-    pif_out.set_pull(Pull::Up);
-
-    unsafe {
-        pio_instr_util::set_pindir(&mut pio.sm0, 1);
-        pio_instr_util::set_pin(&mut pio.sm0, 1);
-    }
-
-    pio.sm0.tx().push((11 << 16) | 1);
-
     let raw_pio = pac::PIO1;
-    raw_pio.irqs(0).inte().write_set(|m| m.set_sm0(true) );
-
-    // int2.set_as_output();
-    // int2.set_drive_strength(gpio::Drive::_4mA);
-    // int2.set_high();
-    // nmi.set_as_output();
-    // nmi.set_drive_strength(gpio::Drive::_4mA);
-    // nmi.set_high();
-
-    defmt::println!("PIF_IN is now high after {} clocks,", ready_clks);
-
-    // nmi.set_low();
-    // cortex_m::asm::delay(1);
-    // nmi.set_high();
-
-    let mut prev_clks = ready_clks as i32;
-    while unsafe { COUNT } == 0 {
-        let clk = clocks(&mut pio) as i32;
-        if clk == prev_clks {
-            break;
-        }
-        prev_clks = clk as i32;
-        Timer::after(Duration::from_millis(1)).await;
-    }
-    Timer::after(Duration::from_millis(100)).await;
 
-    // Power down output pin
-    pio.sm0.set_pin_dirs(Direction::In, &[&pif_out]);
-    pif_out.set_pull(Pull::None);
-    int2.set_low();
-    int2.set_as_input();
-    nmi.set_low();
-    nmi.set_as_input();
-
-
-    println!("Count saw {} requests", unsafe { COUNT });
+    loop {
+        // Console is off. Start both state machines from scratch, so nothing left over from
+        // the last boot is still in the FIFOs or half way through a transfer.
+        let mut cfg_counter = Config::default();
+        cfg_counter.use_program(&loaded_counter, &[]);
+        cfg_counter.shift_in.auto_fill = true;
+        cfg_counter.shift_out.auto_fill = true;
+        cfg_counter.clock_divider = FixedU32::ONE;
+
+        pio.sm1.set_config(&cfg_counter);
+        pio.sm1.clear_fifos();
+        pio.sm1.restart();
+        unsafe {
+            pio_instr_util::set_x(&mut pio.sm1, u32::MAX);
+        }
+        pio.sm1.set_enable(true);
+
+        let mut cfg_process = Config::default();
+        cfg_process.use_program(&process, &[]);
+        cfg_process.set_in_pins(&[&pif_in]);
+        cfg_process.set_set_pins(&[&pif_out]);
+        cfg_process.set_out_pins(&[&pif_out]);
+        cfg_process.out_sticky = true;
+        cfg_process.shift_in.direction = ShiftDirection::Left;
+        cfg_process.shift_in.auto_fill = true;
+        cfg_process.shift_out.auto_fill = true;
+        cfg_process.shift_out.direction = ShiftDirection::Left;
+        cfg_process.clock_divider = FixedU32::ONE;
+
+        pio.sm0.set_config(&cfg_process);
+        pio.sm0.clear_fifos();
+        pio.sm0.restart();
+        pio.sm0.set_enable(true);
 
-    pio.sm0.set_enable(false);
-    raw_pio.irqs(0).inte().write_set(|m| m.set_sm0(false) );
+        unsafe {
+            SI_INSTANCE.pif.boot(region::region(), cic::cic(), false);
+            SI_INSTANCE.write64_pending = None;
+            SI_INSTANCE.requests = 0;
+        }
 
-    prev_clks = ready_clks as i32;
+        if let Response::Read4(word) = unsafe { SI_INSTANCE.pif.handle(Request::Read4(0)) } {
+            defmt::println!("Ready. PIF ROM starts with {:08x}", word);
+        }
 
-    for entry in unsafe { &SI_LOG[..COUNT.min(SI_LOG.len())] } {
-        let diff = match entry.diff.checked_sub(prev_clks) {
-            Some(diff) => diff as i32,
-            None => -1,
-        };
-        prev_clks = entry.diff;
+        // The RCP drives PIF_IN high once the console powers up
+        gpio_pif_in.wait_for_high().await;
+        POWERED.store(true, Ordering::Relaxed);
+        // A reset requested while the console was off doesn't carry over to this boot
+        reset::cancel();
+        let ready_clks = clocks(&mut pio);
+        let rcp_up = Instant::now();
 
-        defmt::println!("{:?}", LogEntry { diff, ..*entry });
-        Timer::after(Duration::from_millis(1)).await;
-    }
-}
+        pif_out.set_pull(Pull::Up);
 
-#[derive(Clone, Copy, defmt::Format)]
-enum SiCommand {
-    Write64 = 0,
-    Read64 = 1,
-    Write4 = 2,
-    Read4 = 3,
-}
+        unsafe {
+            pio_instr_util::set_pindir(&mut pio.sm0, 1);
+            pio_instr_util::set_pin(&mut pio.sm0, 1);
+        }
 
-impl From<u32> for SiCommand {
-    #[inline(always)]
-    fn from(cmd: u32) -> Self {
-        match cmd {
-            0 => SiCommand::Write64,
-            1 => SiCommand::Read64,
-            2 => SiCommand::Write4,
-            3 => SiCommand::Read4,
-            _ => SiCommand::Read4,
+        pio.sm0.tx().push((11 << 16) | 1);
+
+        // Before the interrupt is enabled, as there can only be one producer at a time
+        trace::record(Record::PowerOn(ready_clks));
+        raw_pio.irqs(0).inte().write_set(|m| m.set_sm0(true) );
+
+        // Both are active low
+        int2.set_high();
+        int2.set_as_output();
+        int2.set_drive_strength(gpio::Drive::_4mA);
+        nmi.set_high();
+        nmi.set_as_output();
+        nmi.set_drive_strength(gpio::Drive::_4mA);
+
+        defmt::println!("PIF_IN is now high after {} clocks,", ready_clks);
+
+        // Serve the console until its clock stops, pressing reset whenever asked to
+        let mut prev_clks = ready_clks as i32;
+        let mut prev_tick = Instant::now();
+        loop {
+            match select3(reset::requested(), reset_button.wait_for_low(), Timer::after(Duration::from_millis(100))).await {
+                Either3::First(()) => reset(&mut nmi, &mut int2).await,
+                // The contacts bounce, so a press only counts once it holds, and so does the release
+                Either3::Second(()) => {
+                    if steady(&mut reset_button).await {
+                        reset(&mut nmi, &mut int2).await;
+                        loop {
+                            reset_button.wait_for_high().await;
+                            if steady(&mut reset_button).await {
+                                break;
+                            }
+                        }
+                    }
+                }
+                Either3::Third(()) => {
+                    let clk = clocks(&mut pio) as i32;
+                    if clk == prev_clks {
+                        break;
+                    }
+                    trace::measured_clock(clk.wrapping_sub(prev_clks) as u32, prev_tick.elapsed());
+                    prev_clks = clk;
+                    prev_tick = Instant::now();
+                }
+            }
         }
+
+        // Power down output pins, so we don't back-power the console
+        raw_pio.irqs(0).inte().write_clear(|m| m.set_sm0(true) );
+        pac::DMA.chan_abort().write_value(1 << dma.number());
+        pio.sm0.set_enable(false);
+        pio.sm1.set_enable(false);
+        pio.sm0.set_pin_dirs(Direction::In, &[&pif_out]);
+        pif_out.set_pull(Pull::None);
+        int2.set_low();
+        int2.set_as_input();
+        nmi.set_low();
+        nmi.set_as_input();
+        // Nothing can interrupt this now, with the SI interrupts off
+        unsafe { SI_INSTANCE.pif.power_off() };
+
+        println!("Console off after {} ms, saw {} requests", rcp_up.elapsed().as_millis(), unsafe { SI_INSTANCE.requests });
+
+        // Wait for PIF_IN to drop before re-arming, so we don't mistake a console that is
+        // still powering down for a new boot
+        gpio_pif_in.wait_for_low().await;
+        POWERED.store(false, Ordering::Relaxed);
+        POWERED_OFF.signal(());
     }
 }
 
@@ -0,0 +1,19 @@
+//! Logging for the PIF model, from core 0
+//!
+//! The SI interrupt can't log itself, see `pif_core::event`.
+
+use embassy_time::{Duration, Timer};
+
+/// Log events as they come in. Polls, as core 1 can't wake core 0's tasks without a lock.
+pub async fn log_forever() -> ! {
+    loop {
+        while let Some(event) = pif_core::event::pop() {
+            if event.is_warning() {
+                defmt::warn!("{}", event);
+            } else {
+                defmt::info!("{}", event);
+            }
+        }
+        Timer::after(Duration::from_millis(10)).await;
+    }
+}
@@ -0,0 +1,181 @@
+//! Passive bus sniffer, for watching a stock PIF and RCP talk
+//!
+//! Instead of answering, we sample PIF_IN, PIF_OUT and the SI clock on every falling clock
+//! edge with the `read` program from sniffer.pio, and decode both directions into the same
+//! transactions the responder logs. Our pins are only ever inputs.
+//!
+//! Samples are packed 10 to a word, 3 bits each, and DMA runs them into a ring buffer that
+//! we decode from. Timestamps count SI clocks from the first start bit after power on.
+//!
+//! `read` counts its samples down from u32::MAX, so we top the count up every time we measure
+//! the clock rate. The DMA count isn't topped up: it runs out after u32::MAX words, 10 times
+//! as many samples, and a session that long stops decoding until the console is power cycled.
+
+use embassy_rp::gpio::{Input, Pull};
+use embassy_rp::pac::dma::vals::{DataSize, TreqSel};
+use embassy_rp::dma::Channel;
+use embassy_rp::peripherals::*;
+use embassy_rp::pio::{Config, Instance, Pio, ShiftDirection};
+use embassy_rp::{pac, pio_instr_util, Peripheral};
+use embassy_time::{Duration, Instant, Timer};
+use fixed::FixedU32;
+use pio_proc::pio_file;
+use si_capture::decode::Decoder;
+
+use super::trace::{self, Record};
+use super::FakeIrqs;
+
+const SAMPLES_PER_WORD: usize = 10;
+const BITS_PER_SAMPLE: usize = 3;
+const RING_WORDS: usize = 1024;
+/// log2 of the ring size in bytes, for the DMA's address wrapping
+const RING_BITS: u8 = 12;
+/// Both lines high for all 10 samples in a word
+const IDLE_WORD: u32 = 0x1b6d_b6db;
+/// How often to measure the SI clock rate for the trace
+const CLOCK_RATE_PERIOD: Duration = Duration::from_secs(1);
+
+/// DMA wraps its write address at the ring size, so the ring has to be aligned to it
+#[repr(align(4096))]
+struct Ring([u32; RING_WORDS]);
+
+static mut RING: Ring = Ring([0; RING_WORDS]);
+
+#[embassy_executor::task]
+pub async fn listen_task(dma: DMA_CH3, pio: PIO1, pif_clk: PIN_20, pif_in: PIN_18, pif_out: PIN_19) -> ! {
+    listen(dma, pio, pif_clk, pif_in, pif_out).await
+}
+
+pub async fn listen<DMA, PIO>(
+    dma: impl Peripheral<P = DMA>,
+    pio_periph: PIO,
+    pif_clk: PIN_20,
+    pif_in: PIN_18,
+    pif_out: PIN_19,
+) -> !
+where
+    DMA: Channel,
+    PIO: Instance,
+{
+    let dma = dma.into_ref();
+    let mut pio = Pio::new(pio_periph, FakeIrqs);
+    let mut gpio_pif_in = Input::new(unsafe { pif_in.clone_unchecked() }, Pull::Down);
+
+    // The `read` program samples `in pins, 3` from PIF_IN, so these have to be consecutive
+    let mut pif_in = pio.common.make_pio_pin(pif_in);
+    let mut pif_out = pio.common.make_pio_pin(pif_out);
+    let mut pif_clk = pio.common.make_pio_pin(pif_clk);
+    for pin in [&mut pif_in, &mut pif_out, &mut pif_clk] {
+        pin.set_pull(Pull::None);
+        pin.set_schmitt(true);
+    }
+    // Holds PIF_IN low while the console is off, so we can see it power on
+    pif_in.set_pull(Pull::Down);
+
+    let read = pio_file!(
+        "src/sniffer.pio",
+        select_program("read")
+    );
+    let read = pio.common.load_program(&read.program);
+
+    let dma_ch = pac::DMA.ch(dma.number() as usize);
+    let treq = PIO::PIO_NO * 8 + 4; // RX0
+
+    loop {
+        let mut cfg = Config::default();
+        cfg.use_program(&read, &[]);
+        cfg.set_in_pins(&[&pif_in, &pif_out, &pif_clk]);
+        cfg.shift_in.direction = ShiftDirection::Left;
+        cfg.shift_in.auto_fill = true;
+        cfg.shift_in.threshold = (SAMPLES_PER_WORD * BITS_PER_SAMPLE) as u8;
+        cfg.clock_divider = FixedU32::ONE;
+
+        pio.sm0.set_config(&cfg);
+        pio.sm0.clear_fifos();
+        pio.sm0.restart();
+        unsafe {
+            pio_instr_util::set_y(&mut pio.sm0, u32::MAX);
+        }
+
+        // Ring the RX FIFO into RING, for as long as the SI clock runs
+        let ring = unsafe { RING.0.as_ptr() };
+        dma_ch.read_addr().write_value(PIO::PIO.rxf(0).as_ptr() as u32);
+        dma_ch.write_addr().write_value(ring as u32);
+        dma_ch.trans_count().write_value(u32::MAX);
+        dma_ch.ctrl_trig().write(|w| {
+            w.set_treq_sel(TreqSel(treq));
+            w.set_data_size(DataSize::SIZE_WORD);
+            w.set_incr_read(false);
+            w.set_incr_write(true);
+            w.set_ring_sel(true);
+            w.set_ring_size(RING_BITS);
+            w.set_chain_to(dma.number());
+            w.set_irq_quiet(true);
+            w.set_en(true);
+        });
+
+        pio.sm0.set_enable(true);
+
+        defmt::println!("Listening. Waiting for the console to power on");
+        gpio_pif_in.wait_for_high().await;
+        trace::record(Record::PowerOn(0));
+
+        // `read` starts sampling after the first command's start bit
+        let mut decoder = Decoder::after_start_bit();
+        let mut read = 0u32;
+        let mut idle = 0;
+        let mut rate_start = (Instant::now(), 0);
+
+        // Decode until the SI clock stops
+        loop {
+            if rate_start.0.elapsed() >= CLOCK_RATE_PERIOD {
+                trace::measured_clock(decoder.clk().wrapping_sub(rate_start.1), rate_start.0.elapsed());
+                rate_start = (Instant::now(), decoder.clk());
+                unsafe {
+                    pio_instr_util::set_y(&mut pio.sm0, u32::MAX);
+                }
+            }
+
+            let written = u32::MAX - dma_ch.trans_count().read();
+            if written == read {
+                idle += 1;
+                if idle == 100 {
+                    break;
+                }
+                Timer::after(Duration::from_millis(1)).await;
+                continue;
+            }
+            idle = 0;
+
+            if written - read > RING_WORDS as u32 {
+                defmt::warn!("Sniffer fell behind, lost {} samples", (written - read) as usize * SAMPLES_PER_WORD);
+                read = written;
+                decoder.resync((written as usize * SAMPLES_PER_WORD) as u32);
+                continue;
+            }
+
+            while read != written {
+                let word = unsafe { core::ptr::read_volatile(ring.add(read as usize % RING_WORDS)) };
+                read += 1;
+
+                // Most of the time the bus is idle, skip through that a word at a time
+                if word & IDLE_WORD == IDLE_WORD && decoder.skip_idle(SAMPLES_PER_WORD as u32) {
+                    continue;
+                }
+                for i in 0..SAMPLES_PER_WORD {
+                    let sample = word >> ((SAMPLES_PER_WORD - 1 - i) * BITS_PER_SAMPLE);
+                    if let Some(transaction) = decoder.sample(sample & 1 != 0, sample & 2 != 0) {
+                        trace::record(Record::Transaction(transaction));
+                    }
+                }
+            }
+        }
+
+        pio.sm0.set_enable(false);
+        pac::DMA.chan_abort().write_value(1 << dma.number());
+
+        defmt::println!("Console off after {} SI clocks", decoder.clk());
+
+        gpio_pif_in.wait_for_low().await;
+    }
+}
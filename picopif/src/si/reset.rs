@@ -0,0 +1,34 @@
+//! Reset button emulation
+//!
+//! Pressing reset on a real console doesn't reset anything straight away. The PIF raises
+//! pre-NMI on INT2 so the game can stop touching the cartridge and audio, then pulses NMI
+//! about half a second later, which restarts the CPU at IPL1. Bit 17 of the seed word tells
+//! IPL3 it was a warm reset, so it keeps `osResetType` and the memory it's meant to keep.
+//!
+//! A reset can be requested by the console's own reset button, BOOTSEL, or the network.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Duration;
+
+/// How long the game gets between pre-NMI and NMI
+pub(super) const PRE_NMI: Duration = Duration::from_millis(500);
+/// NMI is edge triggered, it only has to be low long enough for the RCP to see it
+pub(super) const NMI_PULSE: Duration = Duration::from_micros(10);
+/// How long the reset button has to stay pressed or released before we believe it
+pub(super) const DEBOUNCE: Duration = Duration::from_millis(20);
+
+static REQUEST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Press the console's reset button. Ignored while the console is off.
+pub fn request() {
+    REQUEST.signal(());
+}
+
+pub(super) async fn requested() {
+    REQUEST.wait().await
+}
+
+pub(super) fn cancel() {
+    REQUEST.reset();
+}
@@ -0,0 +1,17 @@
+//! Rumble Pak motor changes, for the control connection
+//!
+//! The pak itself is in `pif_core::rumble`.
+
+use embassy_time::{Duration, Timer};
+
+pub use pif_core::rumble::RumbleEvent;
+
+/// Wait for the next motor change. Polls, as core 1 can't wake core 0's tasks without a lock.
+pub async fn next_event() -> RumbleEvent {
+    loop {
+        if let Some(event) = pif_core::rumble::pop_event() {
+            return event;
+        }
+        Timer::after(Duration::from_millis(10)).await;
+    }
+}
@@ -0,0 +1,91 @@
+//! Live log of every SI transaction
+//!
+//! The SI interrupt records each transaction into a lock-free queue, and a task on core 0
+//! streams them out. Nothing is held back for the end of a session, and there is no cap on
+//! how many are logged. If the task falls behind and the queue fills, the transactions that
+//! don't fit are counted, and the count is logged as a gap once there is room again.
+//!
+//! For reads the data words are the PIF's response, for writes they are what the RCP wrote.
+//! The PIF's response to a Write64 is the Read64 that follows it.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_time::{Duration, Timer};
+
+pub use si_capture::Transaction;
+
+use pif_core::spsc::Queue;
+
+#[derive(Clone, Copy)]
+pub enum Record {
+    /// The console powered on, at this SI clock count
+    PowerOn(u32),
+    Transaction(Transaction),
+    /// This many transactions were lost to a full queue
+    Gap(u32),
+}
+
+impl defmt::Format for Record {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            Record::PowerOn(clk) => defmt::write!(fmt, "Power on @ {}", clk),
+            Record::Transaction(t) => {
+                defmt::write!(fmt, "RCP {} {:03x} @ {} ({} cycle wait) {:08x}", t.command(), t.addr(), t.clk, t.wait_count, t.data())
+            }
+            Record::Gap(count) => defmt::write!(fmt, "Gap of {} transactions", count),
+        }
+    }
+}
+
+static RECORDS: Queue<Record, 64> = Queue::new();
+
+/// Transactions that didn't fit, not yet logged as a gap. Only touched by the producer.
+static mut LOST: u32 = 0;
+
+/// Producer side. Only call from the SI interrupts, or while they are disabled.
+pub(super) fn record(record: Record) {
+    unsafe {
+        if LOST != 0 {
+            if RECORDS.push(Record::Gap(LOST)).is_err() {
+                LOST += 1;
+                return;
+            }
+            LOST = 0;
+        }
+        if RECORDS.push(record).is_err() {
+            LOST += 1;
+        }
+    }
+}
+
+/// SI clocks per second, as last measured. 0 until the console has been on for a while.
+static CLOCK_RATE: AtomicU32 = AtomicU32::new(0);
+
+/// Timestamps count SI clocks, this is how to turn them into time
+pub fn clock_rate() -> u32 {
+    CLOCK_RATE.load(Ordering::Relaxed)
+}
+
+/// Record that the SI clock advanced `clocks` in `elapsed`
+pub(super) fn measured_clock(clocks: u32, elapsed: Duration) {
+    if elapsed.as_micros() != 0 {
+        CLOCK_RATE.store((clocks as u64 * 1_000_000 / elapsed.as_micros()) as u32, Ordering::Relaxed);
+    }
+}
+
+/// Wait for the next record. Polls, as core 1 can't wake core 0's tasks without a lock.
+pub async fn next() -> Record {
+    loop {
+        if let Some(record) = RECORDS.pop() {
+            return record;
+        }
+        Timer::after(Duration::from_millis(1)).await;
+    }
+}
+
+/// Stream records through defmt, for when there's no network
+pub async fn log_forever() -> ! {
+    loop {
+        defmt::println!("{}", next().await);
+    }
+}
@@ -0,0 +1,72 @@
+//! Sets the cartridge RTC from an SNTP server, unless the game or host has already set it
+
+use defmt::*;
+use embassy_net::dns::DnsQueryType;
+use embassy_net::udp::{PacketMetadata, UdpSocket};
+use embassy_net::{IpEndpoint, Stack};
+use embassy_time::{with_timeout, Duration, Timer};
+
+use crate::si::rtc;
+
+const SERVER: &str = "pool.ntp.org";
+const NTP_PORT: u16 = 123;
+const LOCAL_PORT: u16 = 4304;
+/// Seconds from the NTP epoch (1900) to the unix epoch (1970)
+const NTP_TO_UNIX: u64 = 2_208_988_800;
+
+#[derive(defmt::Format)]
+enum SntpError {
+    Dns,
+    Socket,
+    Timeout,
+    BadResponse,
+}
+
+#[embassy_executor::task]
+pub async fn sntp_task(stack: &'static Stack<cyw43::NetDriver<'static>>) -> ! {
+    loop {
+        match query(stack).await {
+            Ok(time) => {
+                info!("SNTP time is {}", time);
+                rtc::set_time(time, rtc::Source::Sntp);
+                Timer::after(Duration::from_secs(60 * 60)).await;
+            }
+            Err(e) => {
+                warn!("SNTP failed: {:?}", e);
+                Timer::after(Duration::from_secs(10)).await;
+            }
+        }
+    }
+}
+
+async fn query(stack: &'static Stack<cyw43::NetDriver<'static>>) -> Result<u64, SntpError> {
+    while stack.config_v4().is_none() {
+        Timer::after(Duration::from_millis(50)).await;
+    }
+
+    let address = *stack.dns_query(SERVER, DnsQueryType::A).await.or(Err(SntpError::Dns))?.first().ok_or(SntpError::Dns)?;
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; 64];
+    let mut tx_buffer = [0; 64];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+    socket.bind(LOCAL_PORT).or(Err(SntpError::Socket))?;
+
+    // Version 3, client mode. Everything else can be zero.
+    let mut packet = [0u8; 48];
+    packet[0] = 0x1b;
+    socket.send_to(&packet, IpEndpoint::new(address, NTP_PORT)).await.or(Err(SntpError::Socket))?;
+
+    let (len, _) = with_timeout(Duration::from_secs(5), socket.recv_from(&mut packet))
+        .await
+        .or(Err(SntpError::Timeout))?
+        .or(Err(SntpError::Socket))?;
+
+    // Transmit timestamp, seconds part
+    let seconds = u32::from_be_bytes(packet[40..44].try_into().unwrap()) as u64;
+    if len < 48 || seconds < NTP_TO_UNIX {
+        return Err(SntpError::BadResponse);
+    }
+    Ok(seconds - NTP_TO_UNIX)
+}
@@ -0,0 +1,75 @@
+//! SI transaction stream, on TCP port 4305
+//!
+//! Each connection gets an SI capture, in the format defined by the si-capture crate: a
+//! header saying which firmware and mode made it, then every record from `si::trace` as it
+//! happens. Save it with `si-capture record`.
+//!
+//! With no client connected, records go to the defmt log instead.
+
+use defmt::*;
+use embassy_futures::select::{select, Either};
+use embassy_net::tcp::TcpSocket;
+use embassy_net::Stack;
+use embassy_time::Duration;
+use embedded_io_async::Write;
+use si_capture::{Header, PinMap, MAX_HEADER_SIZE, MAX_RECORD_SIZE};
+
+use crate::si::trace::{self, Record};
+use crate::si::Mode;
+
+const PORT: u16 = 4305;
+
+/// As wired up in main
+const PINS: PinMap = PinMap { clk: 20, pif_in: 18, pif_out: 19, nmi: 21, int2: 22 };
+
+#[embassy_executor::task]
+pub async fn trace_task(stack: &'static Stack<cyw43::NetDriver<'static>>, mode: Mode) -> ! {
+    let mut rx_buffer = [0; 0x10];
+    let mut tx_buffer = [0; 0x800];
+
+    loop {
+        let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+        socket.set_timeout(Some(Duration::from_secs(10)));
+
+        info!("Trace listening on port {}...", PORT);
+        if let Either::First(Err(e)) = select(socket.accept(PORT), trace::log_forever()).await {
+            warn!("accept error: {:?}", e);
+            continue;
+        }
+        info!("Trace connection accepted");
+
+        let e = stream(&mut socket, mode).await;
+        warn!("trace stream error: {:?}", e);
+        socket.close();
+        socket.flush().await.ok();
+    }
+}
+
+async fn stream(socket: &mut TcpSocket<'_>, mode: Mode) -> embassy_net::tcp::Error {
+    let mode = match mode {
+        Mode::Respond => si_capture::Mode::Respond,
+        Mode::Listen => si_capture::Mode::Listen,
+    };
+    let header = Header::new(mode, PINS, trace::clock_rate(), build_id::full_id());
+    let mut buf = [0u8; MAX_HEADER_SIZE];
+    let len = header.encode(&mut buf);
+    if let Err(e) = socket.write_all(&buf[..len]).await {
+        return e;
+    }
+
+    let mut buf = [0u8; MAX_RECORD_SIZE];
+    loop {
+        let len = capture_record(&trace::next().await).encode(&mut buf);
+        if let Err(e) = socket.write_all(&buf[..len]).await {
+            return e;
+        }
+    }
+}
+
+fn capture_record(record: &Record) -> si_capture::Record {
+    match *record {
+        Record::PowerOn(clk) => si_capture::Record::PowerOn(clk),
+        Record::Transaction(t) => si_capture::Record::Transaction(t),
+        Record::Gap(count) => si_capture::Record::Gap(count),
+    }
+}
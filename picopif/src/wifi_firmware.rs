@@ -113,7 +113,7 @@ where
     }
 }
 
-fn open<'a, FLASH, DMA>(p_flash: &'a mut FLASH, p_dma: &'a mut DMA, start: usize, size: usize) -> impl embedded_io_async::Read + embedded_io_async::Seek + 'a  + defmt::Format
+pub(crate) fn open<'a, FLASH, DMA>(p_flash: &'a mut FLASH, p_dma: &'a mut DMA, start: usize, size: usize) -> impl embedded_io_async::Read + embedded_io_async::Seek + 'a  + defmt::Format
 where
     FLASH: Peripheral,
     <FLASH as Peripheral>::P: embassy_rp::flash::Instance,
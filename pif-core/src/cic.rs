@@ -0,0 +1,168 @@
+//! CIC lockout chip emulation
+//!
+//! The real PIF talks to the cartridge's CIC at power on. We aren't wired to it, so we're told
+//! which CIC the cartridge has and answer for it. Boot goes like this:
+//!  1. The PIF writes the CIC's seed word to 0x7e4 and sets bit 7 of 0x7ff. IPL1 polls for
+//!     that bit, then reads the seeds.
+//!  2. IPL2 checksums IPL3 from the cartridge and sets bit 5 (get checksum) of 0x7ff. The PIF
+//!     fetches the CIC's checksum into 0x7f0 - 0x7f7 and sets bit 7 again. IPL2 compares.
+//!  3. IPL2 sets bit 6 to clear PIF RAM, and jumps to IPL3.
+//!
+//! 6105/7105 games can also challenge the CIC at any time: they write 30 nibbles to
+//! 0x7f0 - 0x7fe, set bit 1 of 0x7ff, and expect the CIC's response in their place.
+//!
+//! The seed word at 0x7e4:
+//!  bits 7:0 - IPL2 seed
+//!  bits 15:8 - IPL3 seed
+//!  bit 17 - reset type, 0 for power on, 1 for NMI (reset button)
+//!  bit 18 - osVersion, set by 6101/7102
+//!  bit 19 - ROM type, 0 for cartridge, 1 for 64DD
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+use crate::pif_ram::PifRam;
+
+const SEED_OFFSET: usize = 0x24;
+const CHECKSUM_OFFSET: usize = 0x30;
+const CHALLENGE_OFFSET: usize = 0x30;
+/// Challenge and response length, in bytes
+const CHALLENGE_LEN: usize = 15;
+
+const SEED_RESET_NMI: u32 = 1 << 17;
+const SEED_VERSION: u32 = 1 << 18;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Cic {
+    Nus6101,
+    Nus6102,
+    Nus6103,
+    Nus6105,
+    Nus6106,
+    Nus7101,
+    Nus7102,
+    Nus7103,
+    Nus7105,
+    Nus7106,
+}
+
+impl Cic {
+    /// Look up a CIC by its part number, e.g. 6102
+    pub fn from_number(number: u16) -> Option<Self> {
+        Some(match number {
+            6101 => Cic::Nus6101,
+            6102 => Cic::Nus6102,
+            6103 => Cic::Nus6103,
+            6105 => Cic::Nus6105,
+            6106 => Cic::Nus6106,
+            7101 => Cic::Nus7101,
+            7102 => Cic::Nus7102,
+            7103 => Cic::Nus7103,
+            7105 => Cic::Nus7105,
+            7106 => Cic::Nus7106,
+            _ => return None,
+        })
+    }
+
+    /// The seed word at 0x7e4, for a power on reset
+    pub fn seed(self) -> u32 {
+        match self {
+            Cic::Nus6101 | Cic::Nus7102 => SEED_VERSION | 0x3f3f,
+            Cic::Nus6102 | Cic::Nus7101 => 0x3f3f,
+            Cic::Nus6103 | Cic::Nus7103 => 0x783f,
+            Cic::Nus6105 | Cic::Nus7105 => 0x913f,
+            Cic::Nus6106 | Cic::Nus7106 => 0x853f,
+        }
+    }
+
+    pub fn has_challenge(self) -> bool {
+        matches!(self, Cic::Nus6105 | Cic::Nus7105)
+    }
+
+    /// The 48 bit IPL3 checksum this CIC vouches for
+    pub fn checksum(self) -> u64 {
+        match self {
+            Cic::Nus6101 => 0x45cc_73ee_317a,
+            Cic::Nus6102 | Cic::Nus7101 => 0xa536_c0f1_d859,
+            Cic::Nus6103 | Cic::Nus7103 => 0x586f_d470_9867,
+            Cic::Nus6105 | Cic::Nus7105 => 0x8618_a45b_c2d3,
+            Cic::Nus6106 | Cic::Nus7106 => 0x2bba_d4e6_eb74,
+            Cic::Nus7102 => 0x4416_0ec5_d9af,
+        }
+    }
+}
+
+static CIC: Mutex<Cell<Cic>> = Mutex::new(Cell::new(Cic::Nus6102));
+
+/// Set the cartridge's CIC. Takes effect the next time the console boots.
+pub fn set_cic(cic: Cic) {
+    critical_section::with(|cs| CIC.borrow(cs).set(cic));
+}
+
+pub fn cic() -> Cic {
+    critical_section::with(|cs| CIC.borrow(cs).get())
+}
+
+/// Write the seed word for IPL1
+pub fn write_seed(ram: &mut PifRam, cic: Cic, nmi: bool) {
+    let mut seed = cic.seed();
+    if nmi {
+        seed |= SEED_RESET_NMI;
+    }
+    ram.bytes_mut()[SEED_OFFSET..SEED_OFFSET + 4].copy_from_slice(&seed.to_be_bytes());
+}
+
+/// Write the checksum for IPL2 to compare against
+pub fn write_checksum(ram: &mut PifRam, cic: Cic) {
+    ram.bytes_mut()[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 8].copy_from_slice(&cic.checksum().to_be_bytes());
+}
+
+/// Compute the 6105's response to a challenge, one nibble at a time
+fn cic_6105_response(challenge: &[u8], response: &mut [u8]) {
+    const LUT0: [u8; 16] = [0x4, 0x7, 0xa, 0x7, 0xe, 0x5, 0xe, 0x1, 0xc, 0xf, 0x8, 0xf, 0x6, 0x3, 0x6, 0x9];
+    const LUT1: [u8; 16] = [0x4, 0x1, 0xa, 0x7, 0xe, 0x5, 0xe, 0x1, 0xc, 0x9, 0x8, 0x5, 0x6, 0x3, 0xc, 0x9];
+
+    let mut key = 0xbu8;
+    let mut lut = &LUT0;
+
+    for (rsp, &chl) in response.iter_mut().zip(challenge) {
+        *rsp = key.wrapping_add(chl.wrapping_mul(5)) & 0xf;
+        key = lut[*rsp as usize];
+
+        let sgn = (*rsp >> 3) & 0x1;
+        let mag = if sgn == 1 { !*rsp } else { *rsp } & 0x7;
+        let mut modifier = if mag % 3 == 1 { sgn } else { 1 - sgn };
+        if core::ptr::eq(lut, &LUT1) {
+            match *rsp {
+                0x1 | 0x9 => modifier = 1,
+                0xb | 0xe => modifier = 0,
+                _ => {}
+            }
+        }
+        lut = if modifier == 1 { &LUT1 } else { &LUT0 };
+    }
+}
+
+/// Replace a 6105 challenge with its response
+pub fn challenge(ram: &mut PifRam) {
+    let bytes = ram.bytes_mut();
+
+    let mut challenge = [0u8; CHALLENGE_LEN * 2];
+    for (i, byte) in bytes[CHALLENGE_OFFSET..CHALLENGE_OFFSET + CHALLENGE_LEN].iter().enumerate() {
+        challenge[i * 2] = byte >> 4;
+        challenge[i * 2 + 1] = byte & 0xf;
+    }
+
+    let mut response = [0u8; CHALLENGE_LEN * 2];
+    cic_6105_response(&challenge, &mut response);
+
+    bytes[CHALLENGE_OFFSET - 2] = 0;
+    bytes[CHALLENGE_OFFSET - 1] = 0;
+    for (i, byte) in bytes[CHALLENGE_OFFSET..CHALLENGE_OFFSET + CHALLENGE_LEN].iter_mut().enumerate() {
+        *byte = response[i * 2] << 4 | response[i * 2 + 1];
+    }
+    // The last byte, including the control byte, is always 0
+    bytes[PifRam::CONTROL] = 0;
+}
@@ -0,0 +1,129 @@
+//! The PIF control byte at 0x7ff, and the boot sequence it drives
+//!
+//! The RCP requests things by setting bits, the PIF acknowledges by clearing them:
+//!  bit 0 (0x01) - run the joybus command blocks in PIF RAM
+//!  bit 1 (0x02) - replace the CIC-6105 challenge in PIF RAM with its response
+//!  bit 3 (0x08) - terminate boot. Without it, a real PIF resets the console after ~5 s.
+//!  bit 4 (0x10) - lock out the PIF ROM, only PIF RAM stays visible
+//!  bit 5 (0x20) - get the CIC's IPL3 checksum into PIF RAM
+//!  bit 6 (0x40) - clear PIF RAM
+//!  bit 7 (0x80) - acquire/ack. Only the PIF sets it, to tell IPL1/IPL2 a request is done.
+//!
+//! Boot requests are only honoured in the state that expects them, so a stray write can't
+//! skip the CIC handshake. Every transition is queued as an event.
+
+use crate::cic::{self, Cic};
+use crate::event::{self, Event};
+use crate::joybus::{self, Channels};
+use crate::pif_ram::PifRam;
+
+const JOYBUS: u8 = 0x01;
+const CHALLENGE: u8 = 0x02;
+const TERMINATE_BOOT: u8 = 0x08;
+const LOCKOUT: u8 = 0x10;
+const GET_CHECKSUM: u8 = 0x20;
+const CLEAR_RAM: u8 = 0x40;
+const ACK: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum State {
+    /// Seed written for IPL1, waiting for IPL2 to ask for the checksum
+    WaitGetChecksum,
+    /// Checksum delivered, waiting for IPL2 to clear PIF RAM
+    WaitClearRam,
+    /// IPL3 is running, waiting for it to terminate boot
+    WaitTerminateBoot,
+    /// The game is running
+    Run,
+}
+
+pub struct Control {
+    state: State,
+    rom_locked: bool,
+    /// The CIC we're answering for this boot
+    cic: Cic,
+}
+
+impl Control {
+    pub const fn new() -> Self {
+        Self { state: State::WaitGetChecksum, rom_locked: false, cic: Cic::Nus6102 }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Set once IPL1 locks out the PIF ROM, until the next boot
+    pub fn rom_locked(&self) -> bool {
+        self.rom_locked
+    }
+
+    fn transition(&mut self, state: State) {
+        event::log(Event::Transition(self.state, state));
+        self.state = state;
+    }
+
+    /// Set up PIF RAM for IPL1, as the PIF does at power on, or before NMI for a warm reset
+    pub fn boot(&mut self, ram: &mut PifRam, cic: Cic, nmi: bool) {
+        ram.clear();
+        cic::write_seed(ram, cic, nmi);
+        ram.set_control(ACK);
+        self.rom_locked = false;
+        self.cic = cic;
+        event::log(Event::Boot { cic, nmi });
+        self.transition(State::WaitGetChecksum);
+    }
+
+    /// Called after the RCP writes to PIF RAM, to act on the control byte
+    pub fn ram_written(&mut self, ram: &mut PifRam, channels: &mut Channels) {
+        let mut control = ram.control();
+
+        if control & LOCKOUT != 0 && !self.rom_locked {
+            event::log(Event::RomLockedOut);
+            self.rom_locked = true;
+        }
+
+        match self.state {
+            State::WaitGetChecksum if control & GET_CHECKSUM != 0 => {
+                cic::write_checksum(ram, self.cic);
+                control = (control & !GET_CHECKSUM) | ACK;
+                self.transition(State::WaitClearRam);
+            }
+            State::WaitClearRam if control & CLEAR_RAM != 0 => {
+                ram.clear();
+                control = 0;
+                self.transition(State::WaitTerminateBoot);
+            }
+            State::WaitTerminateBoot if control & TERMINATE_BOOT != 0 => {
+                control &= !TERMINATE_BOOT;
+                self.transition(State::Run);
+            }
+            _ => {
+                let unexpected = control & (TERMINATE_BOOT | GET_CHECKSUM | CLEAR_RAM);
+                if unexpected != 0 {
+                    event::log(Event::Ignored { requests: unexpected, state: self.state });
+                    control &= !unexpected;
+                }
+            }
+        }
+
+        // Joybus and challenges are serviced whenever they are asked for. Games only use them
+        // once booted, but homebrew doesn't always terminate boot.
+        if control & CHALLENGE != 0 {
+            if !self.cic.has_challenge() {
+                event::log(Event::NoChallenge(self.cic));
+            }
+            ram.set_control(control);
+            cic::challenge(ram);
+            control = ram.control();
+        }
+        if control & JOYBUS != 0 {
+            control &= !JOYBUS;
+            ram.set_control(control);
+            joybus::process(ram.bytes_mut(), channels);
+        }
+
+        ram.set_control(control);
+    }
+}
@@ -0,0 +1,185 @@
+//! Virtual N64 controllers, fed with input from the network
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use crate::event::{self, Device, Event};
+use crate::gb_cart::Cartridge;
+use crate::joybus::{respond, Status};
+use crate::mempak::Mempak;
+use crate::pak::{self, Accessory};
+use crate::rumble::RumblePak;
+use crate::transfer_pak::TransferPak;
+
+pub const PORTS: usize = 4;
+
+/// Controller type, as returned by the status command
+const ID_CONTROLLER: [u8; 2] = [0x05, 0x00];
+/// Status byte flags
+const STATUS_PAK: u8 = 0x01;
+const STATUS_NO_PAK: u8 = 0x02;
+const STATUS_ADDRESS_CRC_ERROR: u8 = 0x04;
+
+/// What's plugged into a controller's pak slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PakKind {
+    None = 0,
+    Controller = 1,
+    Rumble = 2,
+    Transfer = 3,
+}
+
+impl TryFrom<u8> for PakKind {
+    type Error = ();
+
+    fn try_from(kind: u8) -> Result<Self, ()> {
+        match kind {
+            0 => Ok(PakKind::None),
+            1 => Ok(PakKind::Controller),
+            2 => Ok(PakKind::Rumble),
+            3 => Ok(PakKind::Transfer),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Latest input for a port, shared between the network and the SI interrupt
+pub struct Input {
+    /// Packed exactly as the read buttons response: buttons (16 bits), stick x, stick y
+    state: AtomicU32,
+    connected: AtomicBool,
+    pak: AtomicU8,
+}
+
+impl Input {
+    const fn new(pak: PakKind) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            connected: AtomicBool::new(false),
+            pak: AtomicU8::new(pak as u8),
+        }
+    }
+}
+
+/// The Controller Pak starts out in the first controller
+static INPUTS: [Input; PORTS] = [
+    Input::new(PakKind::Controller),
+    Input::new(PakKind::None),
+    Input::new(PakKind::None),
+    Input::new(PakKind::None),
+];
+
+/// Update the input for `port` (0-3), plugging the controller in if needed
+pub fn set_input(port: usize, buttons: u16, x: i8, y: i8) {
+    let state = (buttons as u32) << 16 | (x as u8 as u32) << 8 | y as u8 as u32;
+    INPUTS[port].state.store(state, Ordering::Relaxed);
+    INPUTS[port].connected.store(true, Ordering::Relaxed);
+}
+
+pub fn set_connected(port: usize, connected: bool) {
+    INPUTS[port].connected.store(connected, Ordering::Relaxed);
+}
+
+/// Change the accessory in a controller's pak slot. There's only one Controller Pak, so
+/// plugging it in takes it out of whichever controller had it.
+pub fn set_pak(port: usize, pak: PakKind) {
+    if pak == PakKind::Controller {
+        for input in &INPUTS {
+            let _ = input.pak.compare_exchange(PakKind::Controller as u8, PakKind::None as u8, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+    INPUTS[port].pak.store(pak as u8, Ordering::Relaxed);
+}
+
+/// Release all buttons and centre all sticks, leaving the controllers plugged in
+pub fn release_all() {
+    for input in &INPUTS {
+        input.state.store(0, Ordering::Relaxed);
+    }
+}
+
+pub struct Controller {
+    port: usize,
+    mempak: Mempak,
+    rumble: RumblePak,
+    transfer: TransferPak,
+    /// The last accessory command had a bad address CRC
+    crc_error: bool,
+}
+
+impl Controller {
+    pub const fn new(port: usize) -> Self {
+        Self {
+            port,
+            mempak: Mempak::new(),
+            rumble: RumblePak::new(port),
+            transfer: TransferPak::new(),
+            crc_error: false,
+        }
+    }
+
+    /// Put a Game Boy cartridge into this controller's Transfer Pak
+    pub fn insert_gb_cart(&mut self, cart: Cartridge) {
+        self.transfer.insert(cart);
+    }
+
+    /// The console was switched off, and the paks with it
+    pub fn power_off(&mut self) {
+        self.rumble.power_off();
+        self.transfer.power_off();
+    }
+
+    fn pak(&mut self) -> Option<&mut dyn Accessory> {
+        match PakKind::try_from(INPUTS[self.port].pak.load(Ordering::Relaxed)) {
+            Ok(PakKind::Controller) => Some(&mut self.mempak),
+            Ok(PakKind::Rumble) => Some(&mut self.rumble),
+            Ok(PakKind::Transfer) => Some(&mut self.transfer),
+            _ => None,
+        }
+    }
+
+    fn status(&mut self) -> u8 {
+        let mut status = if self.pak().is_some() { STATUS_PAK } else { STATUS_NO_PAK };
+        if core::mem::take(&mut self.crc_error) {
+            status |= STATUS_ADDRESS_CRC_ERROR;
+        }
+        status
+    }
+
+    pub fn execute(&mut self, tx: &[u8], rx: &mut [u8]) -> Status {
+        let input = &INPUTS[self.port];
+        if !input.connected.load(Ordering::Relaxed) {
+            return Status::NoDevice;
+        }
+
+        match tx[0] {
+            // Status, and reset (which doesn't do anything for a controller)
+            0x00 | 0xff => {
+                let status = self.status();
+                respond(rx, &[ID_CONTROLLER[0], ID_CONTROLLER[1], status])
+            }
+            // Read buttons
+            0x01 => respond(rx, &input.state.load(Ordering::Relaxed).to_be_bytes()),
+            // Accessory read/write
+            0x02 | 0x03 => {
+                let (status, crc_ok) = if tx[0] == 0x02 {
+                    pak::read(self.pak(), tx, rx)
+                } else {
+                    pak::write(self.pak(), tx, rx)
+                };
+                if !crc_ok {
+                    let address = u16::from_be_bytes([tx[1], tx[2]]);
+                    event::log(Event::BadAddressCrc { port: self.port as u8, address });
+                    self.crc_error = true;
+                }
+                status
+            }
+            cmd => {
+                let device = Device::Controller(self.port as u8);
+                event::log(Event::UnknownCommand { device, cmd, tx_len: tx.len() as u8 });
+                Status::NoDevice
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Sectors of a save image written since they were last saved
+//!
+//! Marked by the SI interrupt on core 1 and taken by picopif's `flash_store::save_task` on
+//! core 0. One flag per sector, as there is no atomic read-modify-write to keep a shared
+//! bitmask with.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub struct Dirty<const N: usize> {
+    sectors: [AtomicBool; N],
+}
+
+impl<const N: usize> Dirty<N> {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const CLEAN: AtomicBool = AtomicBool::new(false);
+        Self { sectors: [CLEAN; N] }
+    }
+
+    /// Call after writing to the sector
+    pub fn mark(&self, sector: usize) {
+        self.sectors[sector].store(true, Ordering::SeqCst);
+    }
+
+    /// Take the bitmask of sectors written since the last call. A sector written while it's
+    /// being saved is marked again, so it will be saved again.
+    pub fn take(&self) -> u8 {
+        let mut dirty = 0;
+        for (i, sector) in self.sectors.iter().enumerate() {
+            if sector.load(Ordering::SeqCst) {
+                sector.store(false, Ordering::SeqCst);
+                dirty |= 1 << i;
+            }
+        }
+        dirty
+    }
+}
@@ -0,0 +1,145 @@
+//! Cartridge save EEPROM, on joybus channel 4
+//!
+//! Comes in 4 Kbit (64 blocks) and 16 Kbit (256 blocks) sizes, accessed in 8 byte blocks.
+//! The size is stored in flash next to the image, so it's part of the uploaded save.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+use crate::dirty::Dirty;
+use crate::event::{self, Device, Event};
+use crate::joybus::{respond, Status};
+
+pub const BLOCK_SIZE: usize = 8;
+pub const MAX_SIZE: usize = 2048;
+/// Bytes before the image in `STORE`, holding the kind
+const HEADER_SIZE: usize = 4;
+pub const STORE_SIZE: usize = HEADER_SIZE + MAX_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum EepromKind {
+    None = 0,
+    Eeprom4k = 1,
+    Eeprom16k = 2,
+}
+
+impl EepromKind {
+    pub fn size(self) -> usize {
+        match self {
+            EepromKind::None => 0,
+            EepromKind::Eeprom4k => 512,
+            EepromKind::Eeprom16k => 2048,
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            EepromKind::Eeprom16k => 0xc0,
+            _ => 0x80,
+        }
+    }
+}
+
+impl TryFrom<u8> for EepromKind {
+    type Error = ();
+
+    fn try_from(kind: u8) -> Result<Self, ()> {
+        match kind {
+            0 => Ok(EepromKind::None),
+            1 => Ok(EepromKind::Eeprom4k),
+            2 => Ok(EepromKind::Eeprom16k),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The kind, then the image, exactly as stored in flash
+static mut STORE: [u8; STORE_SIZE] = [0; STORE_SIZE];
+static DIRTY: Dirty<1> = Dirty::new();
+
+pub fn kind() -> EepromKind {
+    // Erased flash reads as 0xff, which is no EEPROM
+    EepromKind::try_from(unsafe { STORE[0] }).unwrap_or(EepromKind::None)
+}
+
+/// The kind and image, as stored in flash.
+///
+/// # Safety
+///
+/// Only for loading before the SI interrupt is enabled
+pub unsafe fn store_mut() -> &'static mut [u8; STORE_SIZE] {
+    &mut *addr_of_mut!(STORE)
+}
+
+/// The kind and image, for saving. See `mempak::sector`.
+pub fn store() -> &'static [u8] {
+    unsafe { &*addr_of!(STORE) }
+}
+
+/// Change the kind, from the network
+pub fn set_kind(kind: EepromKind) {
+    unsafe { STORE[0] = kind as u8 };
+    DIRTY.mark(0);
+}
+
+/// Replace part of the image, from the network.
+///
+/// Not synchronised with the SI interrupt on core 1, so a game reading the EEPROM during an
+/// upload can see a mix of old and new blocks. Upload with the console off.
+pub fn write_image(offset: usize, data: &[u8]) {
+    let start = HEADER_SIZE + offset;
+    unsafe { STORE[start..start + data.len()].copy_from_slice(data) };
+    DIRTY.mark(0);
+}
+
+pub fn read_image(offset: usize, data: &mut [u8]) {
+    let start = HEADER_SIZE + offset;
+    unsafe { data.copy_from_slice(&STORE[start..start + data.len()]) };
+}
+
+/// Take whether the store has been written since the last call
+pub fn take_dirty() -> bool {
+    DIRTY.take() != 0
+}
+
+pub struct Eeprom;
+
+impl Eeprom {
+    pub const fn new() -> Self {
+        Self
+    }
+
+    fn block(kind: EepromKind, block: u8) -> usize {
+        HEADER_SIZE + (block as usize * BLOCK_SIZE) % kind.size()
+    }
+
+    pub fn execute(&mut self, tx: &[u8], rx: &mut [u8]) -> Status {
+        let kind = kind();
+        if kind == EepromKind::None {
+            return Status::NoDevice;
+        }
+
+        match tx[0] {
+            // Info
+            0x00 | 0xff => respond(rx, &[0x00, kind.id(), 0x00]),
+            // Read block
+            0x04 if tx.len() == 2 => {
+                let start = Self::block(kind, tx[1]);
+                respond(rx, unsafe { &STORE[start..start + BLOCK_SIZE] })
+            }
+            // Write block
+            0x05 if tx.len() == 2 + BLOCK_SIZE => {
+                let start = Self::block(kind, tx[1]);
+                unsafe { STORE[start..start + BLOCK_SIZE].copy_from_slice(&tx[2..]) };
+                DIRTY.mark(0);
+                // Not busy
+                respond(rx, &[0x00])
+            }
+            cmd => {
+                event::log(Event::UnknownCommand { device: Device::Eeprom, cmd, tx_len: tx.len() as u8 });
+                Status::NoDevice
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Things worth logging that happen while answering the RCP
+//!
+//! `Pif::handle` runs in the SI interrupt on core 1, where it can't log: defmt takes the
+//! global critical section, a spinlock shared with core 0, and waiting on it could make the
+//! PIF miss a response. Instead the model queues what happened, and a task on core 0 pops
+//! and formats it. Without the `defmt` feature nothing is queued, like the logging macros.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::cic::Cic;
+use crate::control::State;
+use crate::spsc::Queue;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Set up for IPL1, at power on, or before NMI for a warm reset
+    Boot { cic: Cic, nmi: bool },
+    Transition(State, State),
+    RomLockedOut,
+    /// Boot requests that aren't expected in this state
+    Ignored { requests: u8, state: State },
+    /// A CIC challenge for a CIC that doesn't have one
+    NoChallenge(Cic),
+    /// A Write4 outside PIF RAM, at this byte address
+    RomWrite { addr: u16, data: u32 },
+    /// A command block that doesn't fit in PIF RAM, starting at this byte
+    BlockOverrun { channel: u8, at: u8 },
+    UnknownCommand { device: Device, cmd: u8, tx_len: u8 },
+    BadAddressCrc { port: u8, address: u16 },
+    /// An accessory read or write with the wrong tx or rx length
+    PakLengths { write: bool, tx_len: u8, rx_len: u8 },
+    RtcSet(u64),
+    RtcIgnoredWrite(u8),
+    RumbleQueueFull(u8),
+    /// This many events were lost to a full queue
+    Dropped(u32),
+}
+
+/// Which joybus device got a command it doesn't know
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Device {
+    Controller(u8),
+    Eeprom,
+    Rtc,
+}
+
+impl Event {
+    /// Whether something went wrong, rather than the PIF doing its job
+    pub fn is_warning(&self) -> bool {
+        !matches!(self, Event::Boot { .. } | Event::Transition(..) | Event::RomLockedOut | Event::RtcSet(_))
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for Event {
+    fn format(&self, fmt: defmt::Formatter) {
+        match *self {
+            Event::Boot { cic, nmi } => {
+                defmt::write!(fmt, "PIF: {} with {}, seed {:08x}", if nmi { "reset" } else { "boot" }, cic, cic.seed())
+            }
+            Event::Transition(from, to) => defmt::write!(fmt, "PIF: {} -> {}", from, to),
+            Event::RomLockedOut => defmt::write!(fmt, "PIF: ROM locked out"),
+            Event::Ignored { requests, state } => defmt::write!(fmt, "PIF: ignoring {:02x} in {}", requests, state),
+            Event::NoChallenge(cic) => defmt::write!(fmt, "PIF: CIC challenge, but {} doesn't support it", cic),
+            Event::RomWrite { addr, data } => defmt::write!(fmt, "Write4 to PIF ROM {:03x} {:08x}", addr, data),
+            Event::BlockOverrun { channel, at } => {
+                defmt::write!(fmt, "joybus: block on channel {} at {:02x} runs past end of PIF RAM", channel, at)
+            }
+            Event::UnknownCommand { device, cmd, tx_len } => {
+                defmt::write!(fmt, "{}: unknown joybus command {:02x} ({} bytes)", device, cmd, tx_len)
+            }
+            Event::BadAddressCrc { port, address } => {
+                defmt::write!(fmt, "controller {}: bad address CRC {:04x}", port, address)
+            }
+            Event::PakLengths { write, tx_len, rx_len } => defmt::write!(
+                fmt,
+                "pak {}: unexpected lengths tx {} rx {}",
+                if write { "write" } else { "read" },
+                tx_len,
+                rx_len
+            ),
+            Event::RtcSet(time) => defmt::write!(fmt, "rtc: game set time to {}", time),
+            Event::RtcIgnoredWrite(block) => defmt::write!(fmt, "rtc: ignored write to block {}", block),
+            Event::RumbleQueueFull(port) => defmt::write!(fmt, "rumble {}: event queue full", port),
+            Event::Dropped(count) => defmt::write!(fmt, "{} PIF events lost", count),
+        }
+    }
+}
+
+/// From the SI interrupt on core 1 to the logger on core 0
+static EVENTS: Queue<Event, 32> = Queue::new();
+
+/// Events that didn't fit, not yet queued as `Dropped`. Only touched by the producer.
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Queue an event for core 0 to log
+pub(crate) fn log(event: Event) {
+    if cfg!(feature = "defmt") {
+        let dropped = DROPPED.load(Ordering::Relaxed);
+        if dropped != 0 {
+            if EVENTS.push(Event::Dropped(dropped)).is_err() {
+                DROPPED.store(dropped + 1, Ordering::Relaxed);
+                return;
+            }
+            DROPPED.store(0, Ordering::Relaxed);
+        }
+        if EVENTS.push(event).is_err() {
+            DROPPED.store(DROPPED.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Take the oldest event, if there is one
+pub fn pop() -> Option<Event> {
+    EVENTS.pop()
+}
@@ -0,0 +1,174 @@
+//! Game Boy cartridge, as seen through a Transfer Pak
+//!
+//! The ROM is read straight out of memory mapped flash, it's far too big for RAM. Save RAM is
+//! kept in RAM and tracked in 4 KB sectors, like the Controller Pak image.
+//!
+//! The same cartridge goes into every Transfer Pak. Each one gets its own `Cartridge`, with its
+//! own MBC banking, but they all share the one save RAM.
+
+use core::ptr::addr_of_mut;
+
+use crate::dirty::Dirty;
+
+pub const SAVE_SIZE: usize = 0x8000;
+pub const SECTOR_SIZE: usize = 0x1000;
+pub const SECTORS: usize = SAVE_SIZE / SECTOR_SIZE;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+/// The smallest cartridge ROM, two banks
+const MIN_ROM_SIZE: usize = 2 * ROM_BANK_SIZE;
+
+static mut SAVE_RAM: [u8; SAVE_SIZE] = [0; SAVE_SIZE];
+static DIRTY: Dirty<SECTORS> = Dirty::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Mbc {
+    None,
+    Mbc1,
+    Mbc3,
+    Mbc5,
+}
+
+#[derive(Clone)]
+pub struct Cartridge {
+    rom: &'static [u8],
+    ram_size: usize,
+    mbc: Mbc,
+    rom_bank: usize,
+    ram_bank: usize,
+    ram_enabled: bool,
+    /// MBC1 banking mode, the upper bank bits select the RAM bank instead of the ROM bank
+    mbc1_ram_mode: bool,
+}
+
+impl Cartridge {
+    /// No cartridge inserted
+    pub const fn empty() -> Self {
+        Self {
+            rom: &[],
+            ram_size: 0,
+            mbc: Mbc::None,
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            mbc1_ram_mode: false,
+        }
+    }
+
+    /// A cartridge with `rom`, trimmed to the size given in its header. Empty if `rom` isn't a
+    /// cartridge we can emulate.
+    pub fn new(rom: &'static [u8]) -> Self {
+        if rom.len() < MIN_ROM_SIZE || rom[0x148] > 8 {
+            info!("No Game Boy cartridge");
+            return Self::empty();
+        }
+
+        let mbc = match rom[0x147] {
+            0x00 | 0x08 | 0x09 => Mbc::None,
+            0x01..=0x03 => Mbc::Mbc1,
+            0x0f..=0x13 => Mbc::Mbc3,
+            0x19..=0x1e => Mbc::Mbc5,
+            kind => {
+                warn!("Unsupported Game Boy cartridge type {:02x}", kind);
+                return Self::empty();
+            }
+        };
+        let rom_size = (MIN_ROM_SIZE << rom[0x148]).min(rom.len());
+        let ram_size = match rom[0x149] {
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03..=0x05 => SAVE_SIZE,
+            _ => 0,
+        };
+        if rom[0x149] > 0x03 {
+            warn!("Game Boy save RAM truncated to {} bytes", SAVE_SIZE);
+        }
+
+        info!("Game Boy cartridge: {}, {} KB ROM, {} KB RAM", mbc, rom_size / 1024, ram_size / 1024);
+        Self { rom: &rom[..rom_size], ram_size, mbc, ..Self::empty() }
+    }
+
+    pub fn inserted(&self) -> bool {
+        !self.rom.is_empty()
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        let address = address as usize;
+        match address {
+            0x0000..=0x3fff => self.rom[address],
+            0x4000..=0x7fff => {
+                let offset = self.rom_bank() * ROM_BANK_SIZE + address - 0x4000;
+                self.rom[offset % self.rom.len()]
+            }
+            // MBC3 RTC registers are selected as RAM banks 0x08 - 0x0c, and aren't RAM
+            0xa000..=0xbfff if self.ram_enabled && self.ram_size != 0 && self.ram_bank < 0x08 => {
+                let offset = self.ram_bank() * RAM_BANK_SIZE + address - 0xa000;
+                unsafe { SAVE_RAM[offset % self.ram_size] }
+            }
+            _ => 0xff,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        let address = address as usize;
+        match (self.mbc, address) {
+            (Mbc::None, 0x0000..=0x7fff) => {}
+            (_, 0x0000..=0x1fff) => self.ram_enabled = value & 0x0f == 0x0a,
+            (Mbc::Mbc1, 0x2000..=0x3fff) => {
+                self.rom_bank = (self.rom_bank & !0x1f) | (value as usize & 0x1f).max(1);
+            }
+            (Mbc::Mbc1, 0x4000..=0x5fff) => self.ram_bank = value as usize & 0x03,
+            (Mbc::Mbc1, 0x6000..=0x7fff) => self.mbc1_ram_mode = value & 0x01 != 0,
+            (Mbc::Mbc3, 0x2000..=0x3fff) => self.rom_bank = (value as usize & 0x7f).max(1),
+            // RAM bank 0-3, 0x08-0x0c select RTC registers, which we don't have
+            (Mbc::Mbc3, 0x4000..=0x5fff) => self.ram_bank = value as usize,
+            (Mbc::Mbc5, 0x2000..=0x2fff) => self.rom_bank = (self.rom_bank & 0x100) | value as usize,
+            (Mbc::Mbc5, 0x3000..=0x3fff) => {
+                self.rom_bank = (self.rom_bank & 0xff) | (value as usize & 0x01) << 8;
+            }
+            (Mbc::Mbc5, 0x4000..=0x5fff) => self.ram_bank = value as usize & 0x0f,
+            (_, 0xa000..=0xbfff) if self.ram_enabled && self.ram_size != 0 && self.ram_bank < 0x08 => {
+                let offset = (self.ram_bank() * RAM_BANK_SIZE + address - 0xa000) % self.ram_size;
+                unsafe { SAVE_RAM[offset] = value };
+                DIRTY.mark(offset / SECTOR_SIZE);
+            }
+            _ => {}
+        }
+    }
+
+    fn rom_bank(&self) -> usize {
+        match self.mbc {
+            Mbc::Mbc1 if !self.mbc1_ram_mode => self.rom_bank | self.ram_bank << 5,
+            _ => self.rom_bank,
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        match self.mbc {
+            Mbc::Mbc1 if !self.mbc1_ram_mode => 0,
+            _ => self.ram_bank,
+        }
+    }
+}
+
+/// Save RAM.
+///
+/// # Safety
+///
+/// Only for loading the save before the SI interrupt is enabled
+pub unsafe fn save_ram_mut() -> &'static mut [u8; SAVE_SIZE] {
+    &mut *addr_of_mut!(SAVE_RAM)
+}
+
+/// A sector of save RAM, for saving. See `mempak::sector`.
+pub fn sector(sector: usize) -> &'static [u8] {
+    let start = sector * SECTOR_SIZE;
+    unsafe { &SAVE_RAM[start..start + SECTOR_SIZE] }
+}
+
+/// Take the bitmask of sectors written since the last call
+pub fn take_dirty() -> u8 {
+    DIRTY.take()
+}
@@ -0,0 +1,142 @@
+//! Joybus command block processing
+//!
+//! When the control byte at 0x7ff has bit 0 set, the PIF walks PIF RAM as a list of command
+//! blocks, one per channel, and runs each one on the device attached to that channel.
+//! A block is a tx byte count, an rx byte count, tx bytes, then space for rx bytes. The
+//! response is written back in place, so the RCP collects it with a Read64.
+//!
+//! Outside of a block, some byte values have special meanings:
+//!  0x00 - skip this channel
+//!  0xfd, 0xfe - end of commands
+//!  0xff - padding, ignored
+
+use crate::controller::{self, Controller};
+use crate::eeprom::Eeprom;
+use crate::event::{self, Event};
+use crate::gb_cart::Cartridge;
+use crate::rtc::Rtc;
+
+/// Number of joybus channels. Channels 0-3 are the controller ports, channel 4 is the cartridge
+pub const CHANNELS: usize = 5;
+const CARTRIDGE: usize = 4;
+
+/// Flag set in a block's rx byte count when nothing answered on the channel
+const RX_NO_DEVICE: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Status {
+    Ok,
+    /// Nothing is connected to the channel
+    NoDevice,
+}
+
+pub trait Bus {
+    /// Run a single command on `channel`. `tx` is the command byte followed by its arguments,
+    /// `rx` is sized by the block's rx byte count and receives the response.
+    fn execute(&mut self, channel: usize, tx: &[u8], rx: &mut [u8]) -> Status;
+}
+
+/// Copy a response into a block's rx bytes, truncating if the RCP asked for fewer
+pub fn respond(rx: &mut [u8], data: &[u8]) -> Status {
+    let len = rx.len().min(data.len());
+    rx[..len].copy_from_slice(&data[..len]);
+    Status::Ok
+}
+
+/// The devices attached to each channel
+pub struct Channels {
+    controllers: [Controller; controller::PORTS],
+    eeprom: Eeprom,
+    rtc: Rtc,
+}
+
+impl Channels {
+    /// `uptime` is seconds since power on, for the RTC
+    pub const fn new(uptime: fn() -> u64) -> Self {
+        Self {
+            controllers: [Controller::new(0), Controller::new(1), Controller::new(2), Controller::new(3)],
+            eeprom: Eeprom::new(),
+            rtc: Rtc::new(uptime),
+        }
+    }
+
+    /// Put the same Game Boy cartridge into every Transfer Pak
+    pub fn insert_gb_cart(&mut self, cart: Cartridge) {
+        for controller in &mut self.controllers {
+            controller.insert_gb_cart(cart.clone());
+        }
+    }
+
+    /// The console was switched off, and everything it powers with it
+    pub fn power_off(&mut self) {
+        for controller in &mut self.controllers {
+            controller.power_off();
+        }
+    }
+}
+
+impl Bus for Channels {
+    fn execute(&mut self, channel: usize, tx: &[u8], rx: &mut [u8]) -> Status {
+        match channel {
+            CARTRIDGE => match tx[0] {
+                0x06..=0x08 => self.rtc.execute(tx, rx),
+                _ => self.eeprom.execute(tx, rx),
+            },
+            _ => self.controllers[channel].execute(tx, rx),
+        }
+    }
+}
+
+/// Walk the command blocks in PIF RAM, excluding the control byte, and run them on `bus`
+pub fn process(ram: &mut [u8; 64], bus: &mut impl Bus) {
+    const END: usize = 0x3f;
+
+    let mut channel = 0;
+    let mut i = 0;
+
+    while i < END && channel < CHANNELS {
+        let tx = ram[i];
+        match tx {
+            0x00 => {
+                channel += 1;
+                i += 1;
+                continue;
+            }
+            0xfd | 0xfe => break,
+            0xff => {
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        if i + 1 >= END || ram[i + 1] == 0xfe {
+            break;
+        }
+
+        let tx_len = (tx & 0x3f) as usize;
+        let rx_len = (ram[i + 1] & 0x3f) as usize;
+        let tx_start = i + 2;
+        let end = tx_start + tx_len + rx_len;
+
+        if end > END {
+            event::log(Event::BlockOverrun { channel: channel as u8, at: i as u8 });
+            break;
+        }
+
+        let (tx_buf, rx_buf) = ram[tx_start..end].split_at_mut(tx_len);
+        let status = if tx_len == 0 {
+            Status::NoDevice
+        } else {
+            bus.execute(channel, tx_buf, rx_buf)
+        };
+
+        if status == Status::NoDevice {
+            ram[i + 1] |= RX_NO_DEVICE;
+        }
+
+        i = end;
+        channel += 1;
+    }
+}
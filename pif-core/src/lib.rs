@@ -0,0 +1,241 @@
+//! The PIF, without the hardware
+//!
+//! Everything picopif knows about answering the RCP: SI command decoding, PIF ROM and RAM, the
+//! control byte and boot handshake, the CIC, and the joybus devices. `Pif::handle` takes one
+//! decoded SI request and returns what the PIF sends back. picopif drives it from the SI
+//! interrupt, and on a host it runs under `cargo test`, or against recorded traces.
+//!
+//! State that picopif shares between its two cores lives in statics, one per process rather
+//! than one per `Pif`: controller input, the Controller Pak image, EEPROM, Game Boy save RAM,
+//! RTC time changes and rumble events, and the configured CIC and region. Two `Pif`s in one
+//! process see the same devices, so tests that use them have to take turns.
+//!
+//! Logging goes to defmt with the `defmt` feature. What happens while answering the RCP is
+//! queued in `event` instead, for core 0 to log.
+
+#![no_std]
+// Everything is built by `const fn new()`, for statics, rather than `Default`
+#![allow(clippy::new_without_default)]
+
+mod fmt;
+
+pub mod cic;
+mod control;
+pub mod controller;
+mod dirty;
+pub mod eeprom;
+pub mod event;
+pub mod gb_cart;
+mod joybus;
+pub mod mempak;
+mod pak;
+pub mod pif_ram;
+pub mod pif_rom;
+pub mod region;
+pub mod rtc;
+pub mod rumble;
+pub mod spsc;
+mod transfer_pak;
+
+pub use control::State;
+
+use cic::Cic;
+use control::Control;
+use event::Event;
+use joybus::Channels;
+use pif_ram::PifRam;
+use pif_rom::PifRom;
+use region::{Region, REGIONS};
+
+/// The four commands the RCP sends over SI, from bits 11:10 of the command packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SiCommand {
+    Write64 = 0,
+    Read64 = 1,
+    Write4 = 2,
+    Read4 = 3,
+}
+
+impl From<u32> for SiCommand {
+    #[inline(always)]
+    fn from(cmd: u32) -> Self {
+        match cmd & 0x3 {
+            0 => SiCommand::Write64,
+            1 => SiCommand::Read64,
+            2 => SiCommand::Write4,
+            _ => SiCommand::Read4,
+        }
+    }
+}
+
+impl SiCommand {
+    /// Decode the command from a 12 bit command packet
+    #[inline(always)]
+    pub fn from_packet(packet: u32) -> Self {
+        Self::from((packet >> 10) & 0x3)
+    }
+
+    /// The word count the command transfers
+    pub fn words(self) -> usize {
+        match self {
+            SiCommand::Write64 | SiCommand::Read64 => 16,
+            SiCommand::Write4 | SiCommand::Read4 => 1,
+        }
+    }
+}
+
+/// PIF word address from a command packet, 0x000 - 0x1ff
+#[inline(always)]
+pub fn word_addr(packet: u32) -> usize {
+    (packet >> 1) as usize & 0x1ff
+}
+
+/// PIF byte address from a command packet, 0x000 - 0x7fc
+#[inline(always)]
+pub fn byte_addr(packet: u32) -> u32 {
+    (packet & 0x3fe) << 1
+}
+
+/// An SI request from the RCP. Addresses are word addresses, as in the command packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request {
+    Read4(usize),
+    Write4(usize, u32),
+    /// 64 byte transfers always cover the whole of PIF RAM, whatever the address
+    Read64,
+    Write64([u32; 16]),
+}
+
+impl Request {
+    /// A read, or a write carrying `data`, from a command packet. `data` needs one word for
+    /// Write4 and 16 for Write64.
+    pub fn from_packet(packet: u32, data: &[u32]) -> Self {
+        match SiCommand::from_packet(packet) {
+            SiCommand::Read4 => Request::Read4(word_addr(packet)),
+            SiCommand::Write4 => Request::Write4(word_addr(packet), data[0]),
+            SiCommand::Read64 => Request::Read64,
+            SiCommand::Write64 => Request::Write64(data[..16].try_into().unwrap()),
+        }
+    }
+}
+
+/// What the PIF sends back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Response {
+    Read4(u32),
+    Read64([u32; 16]),
+    /// Writes have no data to return
+    Written,
+}
+
+/// PIF ROM and RAM, the boot handshake, and the joybus channels. Devices share state with
+/// every other `Pif` in the process, see above.
+pub struct Pif {
+    ram: PifRam,
+    roms: [PifRom; REGIONS],
+    region: Region,
+    channels: Channels,
+    control: Control,
+    /// Keep the PIF ROM readable after lockout, for debugging
+    rom_readable: bool,
+}
+
+impl Pif {
+    /// `uptime` is seconds since power on, which the cartridge RTC counts from
+    pub const fn new(uptime: fn() -> u64) -> Self {
+        Self {
+            ram: PifRam::new(),
+            roms: [PifRom::new(), PifRom::new(), PifRom::new()],
+            region: Region::Ntsc,
+            channels: Channels::new(uptime),
+            control: Control::new(),
+            rom_readable: false,
+        }
+    }
+
+    /// Set up PIF RAM for IPL1, as the PIF does at power on, or before NMI for a warm reset
+    pub fn boot(&mut self, region: Region, cic: Cic, nmi: bool) {
+        self.region = region;
+        self.control.boot(&mut self.ram, region.cic(cic), nmi);
+    }
+
+    /// The console was switched off. Controller paks lose power, so a Rumble Pak's motor stops.
+    pub fn power_off(&mut self) {
+        self.channels.power_off();
+    }
+
+    pub fn handle(&mut self, request: Request) -> Response {
+        match request {
+            Request::Read4(addr) => Response::Read4(self.read4(addr)),
+            Request::Write4(addr, data) => {
+                if PifRam::contains(addr) {
+                    self.ram.write4(addr, data);
+                    self.ram_written();
+                } else {
+                    event::log(Event::RomWrite { addr: (addr << 2) as u16, data });
+                }
+                Response::Written
+            }
+            Request::Read64 => {
+                // Read64 always transfers PIF RAM, which stays visible after ROM lockout
+                let mut data = [0; 16];
+                self.ram.read64(&mut data);
+                Response::Read64(data)
+            }
+            Request::Write64(data) => {
+                self.ram.write64(&data);
+                self.ram_written();
+                Response::Written
+            }
+        }
+    }
+
+    /// Called after the RCP writes to PIF RAM, to act on the control byte at 0x7ff
+    fn ram_written(&mut self) {
+        self.control.ram_written(&mut self.ram, &mut self.channels);
+    }
+
+    /// Read a word of PIF ROM or RAM, as the RCP sees it
+    #[inline(always)]
+    fn read4(&self, addr: usize) -> u32 {
+        if PifRam::contains(addr) {
+            self.ram.read4(addr)
+        } else if self.control.rom_locked() && !self.rom_readable {
+            // Once IPL1 locks out the ROM, a real PIF reads it as zero
+            0
+        } else {
+            self.roms[self.region as usize].read4(addr)
+        }
+    }
+
+    pub fn ram(&self) -> &PifRam {
+        &self.ram
+    }
+
+    pub fn rom_mut(&mut self, region: Region) -> &mut PifRom {
+        &mut self.roms[region as usize]
+    }
+
+    /// Insert a Game Boy cartridge into the Transfer Paks. See `gb_cart::Cartridge::new`.
+    pub fn insert_gb_cart(&mut self, rom: &'static [u8]) {
+        self.channels.insert_gb_cart(gb_cart::Cartridge::new(rom));
+    }
+
+    pub fn set_rom_readable(&mut self, readable: bool) {
+        self.rom_readable = readable;
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Where the boot handshake has got to
+    pub fn state(&self) -> State {
+        self.control.state()
+    }
+
+    pub fn rom_locked(&self) -> bool {
+        self.control.rom_locked()
+    }
+}
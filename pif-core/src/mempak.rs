@@ -0,0 +1,63 @@
+//! Controller Pak, 32 KB of battery backed SRAM
+//!
+//! There is one Controller Pak, which can be plugged into any controller. An image per port
+//! would take half of RAM. The image lives in RAM so the SI interrupt never has to wait on
+//! flash. Writes mark 4 KB sectors dirty, and picopif's `flash_store::save_task` copies them
+//! back to flash.
+
+use core::ptr::addr_of_mut;
+
+use crate::dirty::Dirty;
+use crate::pak::{Accessory, BLOCK_SIZE};
+
+pub const SIZE: usize = 0x8000;
+pub const SECTOR_SIZE: usize = 0x1000;
+pub const SECTORS: usize = SIZE / SECTOR_SIZE;
+
+static mut IMAGE: [u8; SIZE] = [0; SIZE];
+static DIRTY: Dirty<SECTORS> = Dirty::new();
+
+/// The image.
+///
+/// # Safety
+///
+/// Only for loading the image before the SI interrupt is enabled
+pub unsafe fn image_mut() -> &'static mut [u8; SIZE] {
+    &mut *addr_of_mut!(IMAGE)
+}
+
+/// A sector of the image, for saving
+pub fn sector(sector: usize) -> &'static [u8] {
+    let start = sector * SECTOR_SIZE;
+    unsafe { &IMAGE[start..start + SECTOR_SIZE] }
+}
+
+/// Take the bitmask of sectors written since the last call
+pub fn take_dirty() -> u8 {
+    DIRTY.take()
+}
+
+pub struct Mempak;
+
+impl Mempak {
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl Accessory for Mempak {
+    fn read(&mut self, address: u16, data: &mut [u8; BLOCK_SIZE]) {
+        let address = address as usize;
+        if address < SIZE {
+            data.copy_from_slice(unsafe { &IMAGE[address..address + BLOCK_SIZE] });
+        }
+    }
+
+    fn write(&mut self, address: u16, data: &[u8; BLOCK_SIZE]) {
+        let address = address as usize;
+        if address < SIZE {
+            unsafe { IMAGE[address..address + BLOCK_SIZE].copy_from_slice(data) };
+            DIRTY.mark(address / SECTOR_SIZE);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! The controller accessory path, joybus commands 0x02 (read) and 0x03 (write)
+//!
+//! Both commands carry a 16 bit address: the top 11 bits select a 32 byte block, the bottom
+//! 5 bits are a CRC of the block address. Data is followed by an 8 bit CRC so the console
+//! can tell what's in the slot: a response with an inverted CRC means no accessory.
+
+use crate::event::{self, Event};
+use crate::joybus::Status;
+
+pub const BLOCK_SIZE: usize = 32;
+
+/// Something plugged into a controller's pak slot
+pub trait Accessory {
+    fn read(&mut self, address: u16, data: &mut [u8; BLOCK_SIZE]);
+    fn write(&mut self, address: u16, data: &[u8; BLOCK_SIZE]);
+}
+
+/// 5 bit CRC of the top 11 bits of an accessory address
+pub fn address_crc(address: u16) -> u8 {
+    const XOR_TABLE: [u8; 16] = [
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x15, 0x1f, 0x0b,
+        0x16, 0x19, 0x07, 0x0e, 0x1c, 0x0d, 0x1a, 0x01,
+    ];
+
+    (5..16)
+        .filter(|bit| address & (1 << bit) != 0)
+        .fold(0, |crc, bit| crc ^ XOR_TABLE[bit])
+}
+
+/// 8 bit CRC (polynomial 0x85) of a block of accessory data
+pub fn data_crc(data: &[u8; BLOCK_SIZE]) -> u8 {
+    let mut crc = 0u8;
+    // One extra zero byte flushes the last data byte through the register
+    for byte in data.iter().copied().chain([0]) {
+        for bit in (0..8).rev() {
+            let xor = if crc & 0x80 != 0 { 0x85 } else { 0 };
+            crc = (crc << 1) | ((byte >> bit) & 1);
+            crc ^= xor;
+        }
+    }
+    crc
+}
+
+/// Split a command's address into the block address, and whether its CRC was valid
+fn address(tx: &[u8]) -> (u16, bool) {
+    let raw = u16::from_be_bytes([tx[1], tx[2]]);
+    let address = raw & !0x1f;
+    (address, address_crc(address) == (raw & 0x1f) as u8)
+}
+
+/// Run an accessory read, also returning whether the address CRC matched
+pub fn read(pak: Option<&mut dyn Accessory>, tx: &[u8], rx: &mut [u8]) -> (Status, bool) {
+    if tx.len() != 3 || rx.len() != BLOCK_SIZE + 1 {
+        event::log(Event::PakLengths { write: false, tx_len: tx.len() as u8, rx_len: rx.len() as u8 });
+        return (Status::NoDevice, true);
+    }
+
+    let (address, crc_ok) = address(tx);
+    let mut data = [0; BLOCK_SIZE];
+
+    let crc = match pak {
+        Some(pak) if crc_ok => {
+            pak.read(address, &mut data);
+            data_crc(&data)
+        }
+        _ => data_crc(&data) ^ 0xff,
+    };
+
+    rx[..BLOCK_SIZE].copy_from_slice(&data);
+    rx[BLOCK_SIZE] = crc;
+    (Status::Ok, crc_ok)
+}
+
+/// Run an accessory write, also returning whether the address CRC matched
+pub fn write(pak: Option<&mut dyn Accessory>, tx: &[u8], rx: &mut [u8]) -> (Status, bool) {
+    if tx.len() != BLOCK_SIZE + 3 || rx.len() != 1 {
+        event::log(Event::PakLengths { write: true, tx_len: tx.len() as u8, rx_len: rx.len() as u8 });
+        return (Status::NoDevice, true);
+    }
+
+    let (address, crc_ok) = address(tx);
+    let data: &[u8; BLOCK_SIZE] = tx[3..].try_into().unwrap();
+
+    rx[0] = match pak {
+        Some(pak) if crc_ok => {
+            pak.write(address, data);
+            data_crc(data)
+        }
+        _ => data_crc(data) ^ 0xff,
+    };
+    (Status::Ok, crc_ok)
+}
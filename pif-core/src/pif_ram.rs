@@ -0,0 +1,82 @@
+/// PIF RAM, the 64 bytes at the top of the PIF address space (0x7c0 - 0x7ff)
+///
+/// The RCP sees this as 16 big-endian words. Joybus command blocks and the control byte at
+/// 0x7ff are byte oriented, so we store bytes and convert on word access.
+pub struct PifRam {
+    ram: [u8; PifRam::SIZE],
+}
+
+impl PifRam {
+    pub const SIZE: usize = 64;
+    /// Byte address of the first byte of PIF RAM
+    pub const START: usize = 0x7c0;
+    /// Word index of the first word of PIF RAM, as it appears in an SI command
+    pub const START_WORD: usize = Self::START >> 2;
+    /// Offset of the control byte, at 0x7ff
+    pub const CONTROL: usize = Self::SIZE - 1;
+
+    pub const fn new() -> Self {
+        Self { ram: [0; Self::SIZE] }
+    }
+
+    /// True if the SI word index falls within PIF RAM
+    #[inline(always)]
+    pub fn contains(word: usize) -> bool {
+        word >= Self::START_WORD
+    }
+
+    #[inline(always)]
+    fn offset(word: usize) -> usize {
+        (word << 2) & (Self::SIZE - 1)
+    }
+
+    /// Read a single word, addressed by SI word index
+    #[inline(always)]
+    pub fn read4(&self, word: usize) -> u32 {
+        let offset = Self::offset(word);
+        u32::from_be_bytes(self.ram[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Write a single word, addressed by SI word index
+    #[inline(always)]
+    pub fn write4(&mut self, word: usize, data: u32) {
+        let offset = Self::offset(word);
+        self.ram[offset..offset + 4].copy_from_slice(&data.to_be_bytes());
+    }
+
+    /// 64 byte reads always cover the whole of PIF RAM, regardless of address
+    #[inline(always)]
+    pub fn read64(&self, data: &mut [u32; 16]) {
+        for (word, chunk) in data.iter_mut().zip(self.ram.chunks_exact(4)) {
+            *word = u32::from_be_bytes(chunk.try_into().unwrap());
+        }
+    }
+
+    /// 64 byte writes always cover the whole of PIF RAM, regardless of address
+    #[inline(always)]
+    pub fn write64(&mut self, data: &[u32; 16]) {
+        for (word, chunk) in data.iter().zip(self.ram.chunks_exact_mut(4)) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+    }
+
+    pub fn control(&self) -> u8 {
+        self.ram[Self::CONTROL]
+    }
+
+    pub fn set_control(&mut self, control: u8) {
+        self.ram[Self::CONTROL] = control;
+    }
+
+    pub fn clear(&mut self) {
+        self.ram.fill(0);
+    }
+
+    pub fn bytes(&self) -> &[u8; Self::SIZE] {
+        &self.ram
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8; Self::SIZE] {
+        &mut self.ram
+    }
+}
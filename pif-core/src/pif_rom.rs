@@ -0,0 +1,32 @@
+/// The PIF boot ROM, everything in the PIF address space below PIF RAM (0x000 - 0x7bf)
+///
+/// Holds IPL1, which the VR4300 executes directly over SI at reset. The image is loaded
+/// from flash before the console is powered up, as there is no time to touch flash once
+/// the RCP starts fetching.
+pub struct PifRom {
+    rom: [u8; PifRom::SIZE],
+}
+
+impl PifRom {
+    pub const SIZE: usize = 0x7c0;
+
+    pub const fn new() -> Self {
+        Self { rom: [0; Self::SIZE] }
+    }
+
+    /// Read a single word, addressed by SI word index. Must be below PIF RAM.
+    #[inline(always)]
+    pub fn read4(&self, word: usize) -> u32 {
+        let offset = word << 2;
+        u32::from_be_bytes(self.rom[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// An erased flash region reads back as all ones
+    pub fn is_blank(&self) -> bool {
+        self.rom.iter().all(|&b| b == 0xff)
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8; Self::SIZE] {
+        &mut self.rom
+    }
+}
@@ -0,0 +1,77 @@
+//! Console region, which is also the TV type
+//!
+//! Regions differ in the PIF ROM, whose IPL1 hands the TV type on to IPL3 for `osTvType`, and
+//! in the cartridge's CIC: PAL cartridges carry 71xx CICs with their own seeds and checksums.
+//! MPAL (Brazil) cartridges use the NTSC CICs. So the CIC is set once by the cartridge's
+//! part number, and the region picks the matching variant, along with its PIF ROM image.
+
+use core::cell::Cell;
+
+use critical_section::Mutex;
+
+use crate::cic::Cic;
+
+pub const REGIONS: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Region {
+    Ntsc = 0,
+    Pal = 1,
+    Mpal = 2,
+}
+
+impl TryFrom<u8> for Region {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Region::Ntsc),
+            1 => Ok(Region::Pal),
+            2 => Ok(Region::Mpal),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Region {
+    pub const ALL: [Region; REGIONS] = [Region::Ntsc, Region::Pal, Region::Mpal];
+
+    /// Parse a region name, as given in `PICOPIF_REGION`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ntsc" | "NTSC" => Some(Region::Ntsc),
+            "pal" | "PAL" => Some(Region::Pal),
+            "mpal" | "MPAL" => Some(Region::Mpal),
+            _ => None,
+        }
+    }
+
+    /// This region's variant of a CIC
+    pub fn cic(self, cic: Cic) -> Cic {
+        match (self, cic) {
+            (Region::Pal, Cic::Nus6101) => Cic::Nus7102,
+            (Region::Pal, Cic::Nus6102) => Cic::Nus7101,
+            (Region::Pal, Cic::Nus6103) => Cic::Nus7103,
+            (Region::Pal, Cic::Nus6105) => Cic::Nus7105,
+            (Region::Pal, Cic::Nus6106) => Cic::Nus7106,
+            (Region::Ntsc | Region::Mpal, Cic::Nus7102) => Cic::Nus6101,
+            (Region::Ntsc | Region::Mpal, Cic::Nus7101) => Cic::Nus6102,
+            (Region::Ntsc | Region::Mpal, Cic::Nus7103) => Cic::Nus6103,
+            (Region::Ntsc | Region::Mpal, Cic::Nus7105) => Cic::Nus6105,
+            (Region::Ntsc | Region::Mpal, Cic::Nus7106) => Cic::Nus6106,
+            (_, cic) => cic,
+        }
+    }
+}
+
+static REGION: Mutex<Cell<Region>> = Mutex::new(Cell::new(Region::Ntsc));
+
+/// Set the console's region. Takes effect the next time the console boots.
+pub fn set_region(region: Region) {
+    critical_section::with(|cs| REGION.borrow(cs).set(region));
+}
+
+pub fn region() -> Region {
+    critical_section::with(|cs| REGION.borrow(cs).get())
+}
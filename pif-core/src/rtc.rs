@@ -0,0 +1,226 @@
+//! Cartridge real time clock, on joybus channel 4 alongside the EEPROM
+//!
+//! Commands: 0x06 status, 0x07 read block, 0x08 write block. Blocks are 8 bytes:
+//!  0 - control. Byte 0 bit 0 write protects block 1, bit 1 write protects block 2.
+//!      Byte 1 bit 2 stops the clock, so it can be set.
+//!  1 - battery backed scratch RAM
+//!  2 - the time, in BCD: second, minute, hour (bit 7 set for 24 hour), day, weekday,
+//!      month, year, century (0 for 19xx, 1 for 20xx)
+//!
+//! The clock runs off the uptime given to `Pif::new`, offset to the current time. It can be set by the
+//! game, the host over the network, or SNTP. The clock belongs to the SI interrupt on core 1,
+//! the host and SNTP queue their changes to it.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::event::{self, Device, Event};
+use crate::joybus::{respond, Status};
+use crate::spsc::Queue;
+
+const BLOCK_SIZE: usize = 8;
+
+const PROTECT_BLOCK_1: u8 = 0x01;
+const PROTECT_BLOCK_2: u8 = 0x02;
+const CONTROL_STOP: u8 = 0x04;
+const STATUS_STOPPED: u8 = 0x80;
+
+/// 2000-01-01 00:00:00, used until someone sets the clock
+const DEFAULT_TIME: u64 = 946_684_800;
+
+/// Who last set the clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Source {
+    Unset,
+    Game,
+    Host,
+    Sntp,
+}
+
+#[derive(Clone, Copy)]
+struct Clock {
+    /// Seconds since power on
+    uptime: fn() -> u64,
+    /// Unix time at uptime 0
+    offset: u64,
+    /// Unix time the clock was stopped at
+    stopped: Option<u64>,
+    source: Source,
+}
+
+impl Clock {
+    fn now(&self) -> u64 {
+        self.stopped.unwrap_or_else(|| self.offset + (self.uptime)())
+    }
+
+    fn set(&mut self, time: u64, source: Source) {
+        if source == Source::Sntp && !matches!(self.source, Source::Unset | Source::Sntp) {
+            return;
+        }
+        match self.stopped {
+            Some(_) => self.stopped = Some(time),
+            None => self.offset = time.saturating_sub((self.uptime)()),
+        }
+        self.source = source;
+    }
+}
+
+/// Times set from core 0, waiting for the RTC to pick them up
+static SET_TIME: Queue<(u64, Source), 8> = Queue::new();
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Set the clock to a unix time, from core 0. Takes effect the next time the game talks to
+/// the RTC. SNTP won't override a time set by anything else.
+pub fn set_time(time: u64, source: Source) {
+    // An SNTP time already waiting is as good as a new one
+    if source == Source::Sntp && !SET_TIME.is_empty() {
+        return;
+    }
+    if SET_TIME.push((time, source)).is_err() {
+        warn!("rtc: too many times queued, dropping {}", time);
+    }
+}
+
+fn to_bcd(value: u32) -> u8 {
+    (((value / 10) << 4) | (value % 10)) as u8
+}
+
+fn from_bcd(value: u8) -> u32 {
+    (value >> 4) as u32 * 10 + (value & 0x0f) as u32
+}
+
+/// Days since 1970-01-01 to (year, month, day), from Howard Hinnant's date algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// (year, month, day) to days since 1970-01-01
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn encode_time(time: u64) -> [u8; BLOCK_SIZE] {
+    let days = (time / 86_400) as i64;
+    let secs = (time % 86_400) as u32;
+    let (year, month, day) = civil_from_days(days);
+    // 1970-01-01 was a Thursday
+    let weekday = (days + 4).rem_euclid(7) as u32;
+
+    [
+        to_bcd(secs % 60),
+        to_bcd(secs / 60 % 60),
+        to_bcd(secs / 3600) | 0x80,
+        to_bcd(day),
+        to_bcd(weekday),
+        to_bcd(month),
+        to_bcd((year % 100) as u32),
+        to_bcd((year / 100 - 19) as u32),
+    ]
+}
+
+fn decode_time(data: &[u8]) -> u64 {
+    let year = 1900 + from_bcd(data[7]) as i64 * 100 + from_bcd(data[6]) as i64;
+    let days = days_from_civil(year, from_bcd(data[5]).clamp(1, 12), from_bcd(data[3]).max(1));
+    let secs = from_bcd(data[2] & 0x7f) * 3600 + from_bcd(data[1]) * 60 + from_bcd(data[0]);
+    (days.max(0) as u64) * 86_400 + secs as u64
+}
+
+pub struct Rtc {
+    control: [u8; BLOCK_SIZE],
+    ram: [u8; BLOCK_SIZE],
+    clock: Clock,
+}
+
+impl Rtc {
+    pub const fn new(uptime: fn() -> u64) -> Self {
+        Self { control: [PROTECT_BLOCK_1 | PROTECT_BLOCK_2, 0, 0, 0, 0, 0, 0, 0], ram: [0; BLOCK_SIZE], clock: Clock { uptime, offset: DEFAULT_TIME, stopped: None, source: Source::Unset } }
+    }
+
+    fn status(&self) -> u8 {
+        if self.control[1] & CONTROL_STOP != 0 { STATUS_STOPPED } else { 0 }
+    }
+
+    fn write_control(&mut self, data: &[u8]) {
+        self.control.copy_from_slice(data);
+        let stop = self.control[1] & CONTROL_STOP != 0;
+
+        let clock = &mut self.clock;
+        match (stop, clock.stopped) {
+            (true, None) => clock.stopped = Some(clock.now()),
+            (false, Some(time)) => {
+                clock.stopped = None;
+                clock.set(time, clock.source);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn execute(&mut self, tx: &[u8], rx: &mut [u8]) -> Status {
+        if !enabled() {
+            return Status::NoDevice;
+        }
+
+        while let Some((time, source)) = SET_TIME.pop() {
+            self.clock.set(time, source);
+        }
+
+        match tx[0] {
+            // Status
+            0x06 => respond(rx, &[0x00, 0x10, self.status()]),
+            // Read block
+            0x07 if tx.len() == 2 => {
+                let mut response = [0; BLOCK_SIZE + 1];
+                match tx[1] {
+                    0 => response[..BLOCK_SIZE].copy_from_slice(&self.control),
+                    1 => response[..BLOCK_SIZE].copy_from_slice(&self.ram),
+                    2 => response[..BLOCK_SIZE].copy_from_slice(&encode_time(self.clock.now())),
+                    _ => {}
+                }
+                response[BLOCK_SIZE] = self.status();
+                respond(rx, &response)
+            }
+            // Write block
+            0x08 if tx.len() == 2 + BLOCK_SIZE => {
+                let data = &tx[2..];
+                match tx[1] {
+                    0 => self.write_control(data),
+                    1 if self.control[0] & PROTECT_BLOCK_1 == 0 => self.ram.copy_from_slice(data),
+                    2 if self.control[0] & PROTECT_BLOCK_2 == 0 => {
+                        let time = decode_time(data);
+                        event::log(Event::RtcSet(time));
+                        self.clock.set(time, Source::Game);
+                    }
+                    block => event::log(Event::RtcIgnoredWrite(block)),
+                }
+                respond(rx, &[self.status()])
+            }
+            cmd => {
+                event::log(Event::UnknownCommand { device: Device::Rtc, cmd, tx_len: tx.len() as u8 });
+                Status::NoDevice
+            }
+        }
+    }
+}
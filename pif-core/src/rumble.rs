@@ -0,0 +1,69 @@
+//! Rumble Pak
+//!
+//! Reads from 0x8000 - 0x8fff identify the pak with 0x80. Writes to 0xc000 - 0xcfff turn the
+//! motor on (0x01) or off (0x00). Motor changes are queued for the network, so the host can
+//! forward them to a real gamepad.
+
+use crate::event::{self, Event};
+use crate::pak::{Accessory, BLOCK_SIZE};
+use crate::spsc::Queue;
+
+const IDENTIFY: u16 = 0x8000;
+const MOTOR: u16 = 0xc000;
+const REGISTER_MASK: u16 = 0xf000;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RumbleEvent {
+    pub port: u8,
+    pub on: bool,
+}
+
+/// From the SI interrupt on core 1 to the control connection on core 0
+static EVENTS: Queue<RumbleEvent, 8> = Queue::new();
+
+/// Take the oldest motor change, if there is one
+pub fn pop_event() -> Option<RumbleEvent> {
+    EVENTS.pop()
+}
+
+pub struct RumblePak {
+    port: u8,
+    motor: bool,
+}
+
+impl RumblePak {
+    pub const fn new(port: usize) -> Self {
+        Self { port: port as u8, motor: false }
+    }
+
+    /// The motor stops when the console is switched off
+    pub fn power_off(&mut self) {
+        self.set_motor(false);
+    }
+
+    fn set_motor(&mut self, on: bool) {
+        if on != self.motor {
+            self.motor = on;
+            if EVENTS.push(RumbleEvent { port: self.port, on }).is_err() {
+                event::log(Event::RumbleQueueFull(self.port));
+            }
+        }
+    }
+}
+
+impl Accessory for RumblePak {
+    fn read(&mut self, address: u16, data: &mut [u8; BLOCK_SIZE]) {
+        if address & REGISTER_MASK == IDENTIFY {
+            data.fill(0x80);
+        }
+    }
+
+    fn write(&mut self, address: u16, data: &[u8; BLOCK_SIZE]) {
+        if address & REGISTER_MASK != MOTOR {
+            return;
+        }
+
+        self.set_motor(data[BLOCK_SIZE - 1] & 0x01 != 0);
+    }
+}
@@ -0,0 +1,55 @@
+//! Single producer, single consumer queue, for passing data between the two cores
+//!
+//! Only needs atomic loads and stores, which is all the Cortex-M0+ has. The producer owns
+//! `head` and the consumer owns `tail`, so neither side ever waits on the other, and the SI
+//! interrupt on core 1 never waits on anything core 0 is doing.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Holds up to `N - 1` items
+pub struct Queue<T: Copy, const N: usize> {
+    buf: UnsafeCell<MaybeUninit<[T; N]>>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Copy + Send, const N: usize> Sync for Queue<T, N> {}
+
+impl<T: Copy, const N: usize> Queue<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: UnsafeCell::new(MaybeUninit::uninit()),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Producer side. Gives the item back if the queue is full.
+    pub fn push(&self, item: T) -> Result<(), T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % N;
+        if next == self.tail.load(Ordering::Acquire) {
+            return Err(item);
+        }
+        unsafe { (self.buf.get() as *mut T).add(head).write(item) };
+        self.head.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// Consumer side
+    pub fn pop(&self) -> Option<T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        let item = unsafe { (self.buf.get() as *const T).add(tail).read() };
+        self.tail.store((tail + 1) % N, Ordering::Release);
+        Some(item)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,113 @@
+//! Transfer Pak
+//!
+//! Registers, each mirrored over a 4 KB range:
+//!  0x8000 - power: write 0x84 to power on, 0xfe to power off. Reads 0x84 when powered.
+//!  0xa000 - bank: selects which 16 KB of the Game Boy address space appears at 0xc000
+//!  0xb000 - status/mode: write 1 to enable cartridge access. Reads status flags.
+//!  0xc000 - 0xffff: the Game Boy cartridge bus, through the selected bank
+
+use crate::gb_cart::Cartridge;
+use crate::pak::{Accessory, BLOCK_SIZE};
+
+const POWER_ON: u8 = 0x84;
+const POWER_OFF: u8 = 0xfe;
+
+const STATUS_ACCESS: u8 = 0x01;
+const STATUS_WAS_RESET: u8 = 0x04;
+const STATUS_REMOVED: u8 = 0x40;
+const STATUS_POWERED: u8 = 0x80;
+
+const BANK_SIZE: u16 = 0x4000;
+
+pub struct TransferPak {
+    cart: Cartridge,
+    powered: bool,
+    access: bool,
+    bank: u8,
+    /// Set on power on, cleared when the status is read
+    was_reset: bool,
+}
+
+impl TransferPak {
+    pub const fn new() -> Self {
+        Self { cart: Cartridge::empty(), powered: false, access: false, bank: 0, was_reset: false }
+    }
+
+    pub fn insert(&mut self, cart: Cartridge) {
+        self.cart = cart;
+    }
+
+    /// The pak loses power with the console, and has to be powered on again
+    pub fn power_off(&mut self) {
+        self.powered = false;
+        self.access = false;
+    }
+
+    fn status(&mut self) -> u8 {
+        let mut status = 0;
+        if self.powered {
+            status |= STATUS_POWERED;
+        }
+        if self.access {
+            status |= STATUS_ACCESS;
+        }
+        if core::mem::take(&mut self.was_reset) {
+            status |= STATUS_WAS_RESET;
+        }
+        if !self.cart.inserted() {
+            status |= STATUS_REMOVED;
+        }
+        status
+    }
+
+    fn gb_address(&self, address: u16) -> Option<u16> {
+        match address {
+            0xc000..=0xffff if self.powered && self.access && self.cart.inserted() => {
+                Some(self.bank as u16 * BANK_SIZE + (address - 0xc000))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Accessory for TransferPak {
+    fn read(&mut self, address: u16, data: &mut [u8; BLOCK_SIZE]) {
+        match address {
+            0x8000..=0x8fff => data.fill(if self.powered { POWER_ON } else { 0 }),
+            0xb000..=0xbfff => data.fill(self.status()),
+            _ => {
+                if let Some(gb_address) = self.gb_address(address) {
+                    for (i, byte) in data.iter_mut().enumerate() {
+                        *byte = self.cart.read(gb_address.wrapping_add(i as u16));
+                    }
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, address: u16, data: &[u8; BLOCK_SIZE]) {
+        let value = data[BLOCK_SIZE - 1];
+        match address {
+            0x8000..=0x8fff => match value {
+                POWER_ON => {
+                    self.powered = true;
+                    self.was_reset = true;
+                }
+                POWER_OFF => {
+                    self.powered = false;
+                    self.access = false;
+                }
+                _ => {}
+            },
+            0xa000..=0xafff => self.bank = value & 0x03,
+            0xb000..=0xbfff => self.access = value & 0x01 != 0,
+            _ => {
+                if let Some(gb_address) = self.gb_address(address) {
+                    for (i, &byte) in data.iter().enumerate() {
+                        self.cart.write(gb_address.wrapping_add(i as u16), byte);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Joybus devices, driven through command blocks in PIF RAM, for the device tests
+//!
+//! Expected CRCs were worked through libdragon's `__calc_address_crc` and `__calc_data_crc`.
+
+// Each test file uses a different part of this
+#![allow(dead_code)]
+
+use std::sync::{Mutex, MutexGuard};
+
+use pif_core::cic::Cic;
+use pif_core::controller::{self, PakKind, PORTS};
+use pif_core::region::Region;
+use pif_core::{Pif, Request, Response};
+
+/// Flag in a block's rx byte count when nothing answered
+pub const NO_DEVICE: u8 = 0x80;
+pub const CARTRIDGE: usize = 4;
+
+/// A booted PIF to send command blocks to. Device state is process wide, see `pif_core`, so
+/// only one exists at a time, and the controllers are put back as they started once it's
+/// dropped.
+pub struct Joybus {
+    pub pif: Pif,
+    _devices: MutexGuard<'static, ()>,
+}
+
+impl Joybus {
+    pub fn new() -> Self {
+        static DEVICES: Mutex<()> = Mutex::new(());
+        let devices = DEVICES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let mut pif = Pif::new(|| 0);
+        pif.boot(Region::Ntsc, Cic::Nus6102, false);
+        Self { pif, _devices: devices }
+    }
+
+    /// With a controller on `port`, with `pak` in it
+    pub fn plug(port: usize, pak: PakKind) -> Self {
+        let joybus = Self::new();
+        controller::set_connected(port, true);
+        controller::set_pak(port, pak);
+        joybus
+    }
+
+    /// Run one command on `channel`, returning the block's rx byte count and its rx bytes
+    pub fn command(&mut self, channel: usize, tx: &[u8], rx_len: usize) -> (u8, Vec<u8>) {
+        let mut ram = [0; 64];
+        let block = channel + 2 + tx.len();
+        ram[channel] = tx.len() as u8;
+        ram[channel + 1] = rx_len as u8;
+        ram[channel + 2..block].copy_from_slice(tx);
+        ram[block..block + rx_len].fill(0xff);
+        ram[block + rx_len] = 0xfe;
+        ram[63] = 0x01;
+
+        let mut data = [0; 16];
+        for (word, chunk) in data.iter_mut().zip(ram.chunks_exact(4)) {
+            *word = u32::from_be_bytes(chunk.try_into().unwrap());
+        }
+        assert_eq!(self.pif.handle(Request::Write64(data)), Response::Written);
+
+        let Response::Read64(data) = self.pif.handle(Request::Read64) else { unreachable!() };
+        let ram: Vec<u8> = data.iter().flat_map(|word| word.to_be_bytes()).collect();
+        (ram[channel + 1], ram[block..block + rx_len].to_vec())
+    }
+
+    pub fn status(&mut self, port: usize) -> Vec<u8> {
+        self.command(port, &[0x00], 3).1
+    }
+
+    pub fn pak_read(&mut self, port: usize, address: u16) -> ([u8; 32], u8) {
+        let [high, low] = address.to_be_bytes();
+        let rx = self.command(port, &[0x02, high, low], 33).1;
+        (rx[..32].try_into().unwrap(), rx[32])
+    }
+
+    pub fn pak_write(&mut self, port: usize, address: u16, data: [u8; 32]) -> u8 {
+        let [high, low] = address.to_be_bytes();
+        let mut tx = vec![0x03, high, low];
+        tx.extend(data);
+        self.command(port, &tx, 1).1[0]
+    }
+}
+
+impl Drop for Joybus {
+    fn drop(&mut self) {
+        for port in 0..PORTS {
+            controller::set_connected(port, false);
+            controller::set_pak(port, PakKind::None);
+        }
+        controller::set_pak(0, PakKind::Controller);
+    }
+}
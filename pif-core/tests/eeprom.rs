@@ -0,0 +1,32 @@
+//! Cartridge EEPROM, on the cartridge's joybus channel
+
+mod common;
+
+use common::{Joybus, CARTRIDGE, NO_DEVICE};
+use pif_core::eeprom::{self, EepromKind};
+
+#[test]
+fn sizes_and_wrap() {
+    let mut joybus = Joybus::new();
+    eeprom::write_image(0, &[0; eeprom::MAX_SIZE]);
+
+    eeprom::set_kind(EepromKind::None);
+    assert_eq!(joybus.command(CARTRIDGE, &[0x00], 3).0, 3 | NO_DEVICE);
+
+    eeprom::set_kind(EepromKind::Eeprom4k);
+    assert_eq!(joybus.command(CARTRIDGE, &[0x00], 3), (3, vec![0x00, 0x80, 0x00]));
+    let block = [1, 2, 3, 4, 5, 6, 7, 8];
+    let mut tx = vec![0x05, 1];
+    tx.extend(block);
+    assert_eq!(joybus.command(CARTRIDGE, &tx, 1).1, [0x00]);
+    assert_eq!(joybus.command(CARTRIDGE, &[0x04, 1], 8).1, block);
+    // 4 Kbit is 64 blocks, and wraps
+    assert_eq!(joybus.command(CARTRIDGE, &[0x04, 65], 8).1, block);
+
+    eeprom::set_kind(EepromKind::Eeprom16k);
+    assert_eq!(joybus.command(CARTRIDGE, &[0x00], 3), (3, vec![0x00, 0xc0, 0x00]));
+    assert_eq!(joybus.command(CARTRIDGE, &[0x04, 1], 8).1, block);
+    assert_eq!(joybus.command(CARTRIDGE, &[0x04, 65], 8).1, [0; 8]);
+
+    eeprom::set_kind(EepromKind::None);
+}
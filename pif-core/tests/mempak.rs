@@ -0,0 +1,42 @@
+//! The Controller Pak, through a controller's accessory commands
+
+mod common;
+
+use common::Joybus;
+use pif_core::controller::{self, PakKind};
+
+/// 0x0020, with its address CRC in the low 5 bits
+const MEMPAK_0020: u16 = 0x0035;
+
+#[test]
+fn controller_pak_crcs() {
+    let mut joybus = Joybus::plug(0, PakKind::Controller);
+
+    let data: [u8; 32] = core::array::from_fn(|i| i as u8);
+    assert_eq!(joybus.pak_write(0, MEMPAK_0020, data), 0x33);
+    assert_eq!(joybus.pak_read(0, MEMPAK_0020), (data, 0x33));
+    assert_eq!(joybus.status(0), [0x05, 0x00, 0x01]);
+
+    // A bad address CRC reads nothing, with an inverted data CRC, and is flagged in the next
+    // status, once
+    assert_eq!(joybus.pak_read(0, 0x0020), ([0; 32], 0xff));
+    assert_eq!(joybus.pak_write(0, 0x0020, [0; 32]), 0xff);
+    assert_eq!(joybus.status(0), [0x05, 0x00, 0x05]);
+    assert_eq!(joybus.status(0), [0x05, 0x00, 0x01]);
+    assert_eq!(joybus.pak_read(0, MEMPAK_0020), (data, 0x33));
+
+    // An empty slot answers with an inverted CRC too
+    controller::set_pak(0, PakKind::None);
+    assert_eq!(joybus.pak_read(0, MEMPAK_0020), ([0; 32], 0xff));
+    assert_eq!(joybus.status(0), [0x05, 0x00, 0x02]);
+}
+
+#[test]
+fn one_controller_pak() {
+    let mut joybus = Joybus::plug(0, PakKind::Controller);
+    controller::set_connected(3, true);
+    controller::set_pak(3, PakKind::Controller);
+
+    assert_eq!(joybus.status(0), [0x05, 0x00, 0x02]);
+    assert_eq!(joybus.status(3), [0x05, 0x00, 0x01]);
+}
@@ -0,0 +1,158 @@
+//! The PIF model, driven with SI requests as the RCP would send them
+
+use pif_core::cic::Cic;
+use pif_core::controller;
+use pif_core::region::Region;
+use pif_core::{Pif, Request, Response, SiCommand, State};
+
+/// Word addresses in PIF RAM
+const SEED: usize = 0x7e4 >> 2;
+const CHECKSUM: usize = 0x7f0 >> 2;
+const CONTROL: usize = 0x7fc >> 2;
+
+fn pif() -> Pif {
+    let mut pif = Pif::new(|| 0);
+    pif.rom_mut(Region::Ntsc).bytes_mut()[..4].copy_from_slice(&0x3c09_3400u32.to_be_bytes());
+    pif.boot(Region::Ntsc, Cic::Nus6102, false);
+    pif
+}
+
+fn read4(pif: &mut Pif, addr: usize) -> u32 {
+    match pif.handle(Request::Read4(addr)) {
+        Response::Read4(data) => data,
+        response => panic!("Read4 got {response:?}"),
+    }
+}
+
+fn read64(pif: &mut Pif) -> [u8; 64] {
+    match pif.handle(Request::Read64) {
+        Response::Read64(data) => {
+            let mut bytes = [0; 64];
+            for (chunk, word) in bytes.chunks_exact_mut(4).zip(data) {
+                chunk.copy_from_slice(&word.to_be_bytes());
+            }
+            bytes
+        }
+        response => panic!("Read64 got {response:?}"),
+    }
+}
+
+fn write64(pif: &mut Pif, bytes: &[u8; 64]) {
+    let mut data = [0; 16];
+    for (word, chunk) in data.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    assert_eq!(pif.handle(Request::Write64(data)), Response::Written);
+}
+
+#[test]
+fn decodes_command_packets() {
+    assert_eq!(SiCommand::from_packet(0xc01), SiCommand::Read4);
+    assert_eq!(Request::from_packet(0xc01 | (0x1f9 << 1), &[]), Request::Read4(0x1f9));
+    assert_eq!(Request::from_packet(0x800 | (0x1ff << 1), &[0x20]), Request::Write4(0x1ff, 0x20));
+    assert_eq!(Request::from_packet(0x400 | (0x1f0 << 1), &[]), Request::Read64);
+    assert_eq!(pif_core::byte_addr(0xc01 | (0x1f9 << 1)), 0x7e4);
+}
+
+#[test]
+fn boot_writes_seed_and_ack() {
+    let mut pif = pif();
+    assert_eq!(read4(&mut pif, SEED), 0x3f3f);
+    assert_eq!(read4(&mut pif, CONTROL) & 0xff, 0x80);
+    assert_eq!(pif.state(), State::WaitGetChecksum);
+
+    pif.boot(Region::Pal, Cic::Nus6102, true);
+    // PAL boards carry the 7101, and NMI sets bit 17
+    assert_eq!(read4(&mut pif, SEED), 0x2_3f3f);
+    assert_eq!(pif.region(), Region::Pal);
+}
+
+#[test]
+fn rom_reads_zero_after_lockout() {
+    let mut pif = pif();
+    assert_eq!(read4(&mut pif, 0), 0x3c09_3400);
+
+    pif.handle(Request::Write4(CONTROL, 0x10));
+    assert!(pif.rom_locked());
+    assert_eq!(read4(&mut pif, 0), 0);
+
+    pif.set_rom_readable(true);
+    assert_eq!(read4(&mut pif, 0), 0x3c09_3400);
+}
+
+#[test]
+fn write4_to_rom_is_ignored() {
+    let mut pif = pif();
+    assert_eq!(pif.handle(Request::Write4(0, 0)), Response::Written);
+    assert_eq!(read4(&mut pif, 0), 0x3c09_3400);
+}
+
+#[test]
+fn boot_handshake() {
+    let mut pif = pif();
+
+    // Terminating boot before the checksum is out of order, and ignored
+    pif.handle(Request::Write4(CONTROL, 0x08));
+    assert_eq!(pif.state(), State::WaitGetChecksum);
+
+    pif.handle(Request::Write4(CONTROL, 0x20));
+    assert_eq!(pif.state(), State::WaitClearRam);
+    assert_eq!(read4(&mut pif, CHECKSUM), 0x0000_a536);
+    assert_eq!(read4(&mut pif, CHECKSUM + 1), 0xc0f1_d859);
+    assert_eq!(read4(&mut pif, CONTROL) & 0xff, 0x80);
+
+    pif.handle(Request::Write4(CONTROL, 0x40));
+    assert_eq!(pif.state(), State::WaitTerminateBoot);
+    assert_eq!(read64(&mut pif), [0; 64]);
+
+    pif.handle(Request::Write4(CONTROL, 0x08));
+    assert_eq!(pif.state(), State::Run);
+    assert_eq!(read4(&mut pif, CONTROL), 0);
+}
+
+#[test]
+fn cic_6105_challenge() {
+    // Worked through mupen64plus's n64_cic_nus_6105, which most emulators take theirs from
+    const CHALLENGE: [u8; 15] = [
+        0x5a, 0x1c, 0x38, 0xe7, 0x02, 0x9f, 0xb4, 0x61, 0xd0, 0x4e, 0x73, 0x8a, 0xc5, 0x16, 0xf2,
+    ];
+    const RESPONSE: [u8; 15] = [
+        0x40, 0x95, 0x46, 0x41, 0x7b, 0xc1, 0xea, 0x63, 0x8c, 0xae, 0x98, 0x40, 0x0d, 0x8a, 0x31,
+    ];
+
+    let mut pif = Pif::new(|| 0);
+    pif.boot(Region::Ntsc, Cic::Nus6105, false);
+
+    let mut ram = [0; 64];
+    ram[0x2e..0x30].fill(0xff);
+    ram[0x30..0x3f].copy_from_slice(&CHALLENGE);
+    ram[63] = 0x02;
+    write64(&mut pif, &ram);
+
+    let ram = read64(&mut pif);
+    assert_eq!(ram[0x2e..0x30], [0, 0]);
+    assert_eq!(ram[0x30..0x3f], RESPONSE);
+    assert_eq!(ram[63], 0);
+}
+
+#[test]
+fn joybus_controller_status_and_buttons() {
+    let mut pif = pif();
+    controller::set_input(0, 0x8000, 12, -5);
+    controller::set_connected(1, false);
+
+    let mut ram = [0; 64];
+    // Port 1: status. Port 2: read buttons. Then end of commands.
+    ram[..6].copy_from_slice(&[0x01, 0x03, 0x00, 0xff, 0xff, 0xff]);
+    ram[6..13].copy_from_slice(&[0x01, 0x04, 0x01, 0xff, 0xff, 0xff, 0xff]);
+    ram[13] = 0xfe;
+    ram[63] = 0x01;
+    write64(&mut pif, &ram);
+
+    let ram = read64(&mut pif);
+    // A controller with a Controller Pak plugged in
+    assert_eq!(ram[3..6], [0x05, 0x00, 0x01]);
+    // Nothing on port 2
+    assert_eq!(ram[7] & 0x80, 0x80);
+    assert_eq!(ram[63] & 0x01, 0);
+}
@@ -0,0 +1,57 @@
+//! The cartridge real time clock, on the cartridge's joybus channel
+
+mod common;
+
+use common::{Joybus, CARTRIDGE, NO_DEVICE};
+use pif_core::rtc::{self, Source};
+
+/// Read a cartridge RTC block, returning it and the status byte after it
+fn rtc_read(joybus: &mut Joybus, block: u8) -> ([u8; 8], u8) {
+    let rx = joybus.command(CARTRIDGE, &[0x07, block], 9).1;
+    (rx[..8].try_into().unwrap(), rx[8])
+}
+
+fn rtc_write(joybus: &mut Joybus, block: u8, data: [u8; 8]) -> u8 {
+    let mut tx = vec![0x08, block];
+    tx.extend(data);
+    joybus.command(CARTRIDGE, &tx, 1).1[0]
+}
+
+#[test]
+fn bcd_and_write_protect() {
+    // 2024-02-29 13:45:30, a Thursday, with the 24 hour bit
+    const LEAP_DAY: [u8; 8] = [0x30, 0x45, 0x93, 0x29, 0x04, 0x02, 0x24, 0x01];
+    // 2000-01-01 00:00:00, a Saturday
+    const Y2K: [u8; 8] = [0x00, 0x00, 0x80, 0x01, 0x06, 0x01, 0x00, 0x01];
+
+    let mut joybus = Joybus::new();
+
+    rtc::set_enabled(false);
+    assert_eq!(joybus.command(CARTRIDGE, &[0x06], 3).0, 3 | NO_DEVICE);
+    rtc::set_enabled(true);
+    assert_eq!(joybus.command(CARTRIDGE, &[0x06], 3).1, [0x00, 0x10, 0x00]);
+    assert_eq!(rtc_read(&mut joybus, 2), (Y2K, 0x00));
+
+    // Blocks 1 and 2 start out write protected
+    rtc_write(&mut joybus, 1, [0xaa; 8]);
+    rtc_write(&mut joybus, 2, LEAP_DAY);
+    assert_eq!(rtc_read(&mut joybus, 1), ([0; 8], 0x00));
+    assert_eq!(rtc_read(&mut joybus, 2), (Y2K, 0x00));
+
+    // Unprotect and stop the clock, to set it
+    assert_eq!(rtc_write(&mut joybus, 0, [0x00, 0x04, 0, 0, 0, 0, 0, 0]), 0x80);
+    rtc_write(&mut joybus, 1, [0xaa; 8]);
+    rtc_write(&mut joybus, 2, LEAP_DAY);
+    assert_eq!(rtc_read(&mut joybus, 1), ([0xaa; 8], 0x80));
+    assert_eq!(rtc_read(&mut joybus, 2), (LEAP_DAY, 0x80));
+
+    // Uptime stands still, so the restarted clock reads the same
+    assert_eq!(rtc_write(&mut joybus, 0, [0x03, 0x00, 0, 0, 0, 0, 0, 0]), 0x00);
+    assert_eq!(rtc_read(&mut joybus, 2), (LEAP_DAY, 0x00));
+
+    // 1999-12-31 23:59:59, a Friday in the 1900s, from the host
+    rtc::set_time(946_684_799, Source::Host);
+    assert_eq!(rtc_read(&mut joybus, 2), ([0x59, 0x59, 0xa3, 0x31, 0x05, 0x12, 0x99, 0x00], 0x00));
+
+    rtc::set_enabled(false);
+}
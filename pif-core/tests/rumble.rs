@@ -0,0 +1,48 @@
+//! The Rumble Pak, through a controller's accessory commands
+
+mod common;
+
+use common::Joybus;
+use pif_core::controller::PakKind;
+use pif_core::rumble;
+
+/// Register addresses with their CRCs in the low 5 bits
+const IDENTIFY: u16 = 0x8001;
+const MOTOR: u16 = 0xc01b;
+
+#[test]
+fn rumble_pak() {
+    let mut joybus = Joybus::plug(1, PakKind::Rumble);
+    while rumble::pop_event().is_some() {}
+
+    assert_eq!(joybus.pak_read(1, IDENTIFY), ([0x80; 32], 0xb8));
+
+    assert_eq!(joybus.pak_write(1, MOTOR, [0x01; 32]), 0xeb);
+    let event = rumble::pop_event().unwrap();
+    assert_eq!((event.port, event.on), (1, true));
+
+    // Only changes are queued
+    joybus.pak_write(1, MOTOR, [0x01; 32]);
+    assert!(rumble::pop_event().is_none());
+
+    assert_eq!(joybus.pak_write(1, MOTOR, [0x00; 32]), 0x00);
+    let event = rumble::pop_event().unwrap();
+    assert_eq!((event.port, event.on), (1, false));
+}
+
+#[test]
+fn motor_stops_at_power_off() {
+    let mut joybus = Joybus::plug(1, PakKind::Rumble);
+    joybus.pak_write(1, MOTOR, [0x01; 32]);
+    while rumble::pop_event().is_some() {}
+
+    joybus.pif.power_off();
+    let event = rumble::pop_event().unwrap();
+    assert_eq!((event.port, event.on), (1, false));
+
+    // And it's off for the next boot, so turning it on is a change again
+    joybus.pif.power_off();
+    assert!(rumble::pop_event().is_none());
+    joybus.pak_write(1, MOTOR, [0x01; 32]);
+    assert!(rumble::pop_event().unwrap().on);
+}
@@ -0,0 +1,108 @@
+//! The Transfer Pak, and the Game Boy cartridge in it, through a controller's accessory commands
+
+mod common;
+
+use common::Joybus;
+use pif_core::controller::PakKind;
+
+/// Register addresses with their CRCs in the low 5 bits
+const REGISTER_8000: u16 = 0x8001;
+const REGISTER_A000: u16 = 0xa00c;
+const REGISTER_B000: u16 = 0xb010;
+const REGISTER_C000: u16 = 0xc01b;
+const REGISTER_E000: u16 = 0xe016;
+
+/// A Game Boy ROM with every byte of each 16 KB bank set to its bank number
+fn gb_rom(size: usize, mbc: u8, rom_size: u8, ram_size: u8) -> &'static [u8] {
+    let mut rom: Vec<u8> = (0..size).map(|i| (i / 0x4000) as u8).collect();
+    rom[0x147] = mbc;
+    rom[0x148] = rom_size;
+    rom[0x149] = ram_size;
+    Box::leak(rom.into_boxed_slice())
+}
+
+/// Power the pak on and enable cartridge access
+fn power_on(joybus: &mut Joybus) {
+    joybus.pak_write(2, REGISTER_8000, [0x84; 32]);
+    joybus.pak_write(2, REGISTER_B000, [0x01; 32]);
+}
+
+/// Write a byte over a whole block of the Game Boy's address space, through Transfer Pak bank
+/// `bank`. Each bank is 16 KB of Game Boy addresses, at 0xc000.
+fn gb_write(joybus: &mut Joybus, bank: u8, address: u16, value: u8) {
+    joybus.pak_write(2, REGISTER_A000, [bank; 32]);
+    joybus.pak_write(2, address, [value; 32]);
+}
+
+#[test]
+fn mbc1_banking() {
+    let mut joybus = Joybus::plug(2, PakKind::Transfer);
+    // MBC1, 64 KB
+    joybus.pif.insert_gb_cart(gb_rom(0x10000, 0x01, 0x01, 0x00));
+
+    assert_eq!(joybus.pak_read(2, REGISTER_8000), ([0x00; 32], 0x00));
+    assert_eq!(joybus.pak_write(2, REGISTER_8000, [0x84; 32]), 0x1e);
+    assert_eq!(joybus.pak_read(2, REGISTER_8000), ([0x84; 32], 0x1e));
+    assert_eq!(joybus.pak_write(2, REGISTER_B000, [0x01; 32]), 0xeb);
+
+    // Powered, access enabled, and reset since the last read
+    assert_eq!(joybus.pak_read(2, REGISTER_B000), ([0x85; 32], 0xf5));
+    assert_eq!(joybus.pak_read(2, REGISTER_B000), ([0x81; 32], 0x53));
+
+    // Transfer Pak bank 0 is Game Boy 0x0000 - 0x3fff, ROM bank 0
+    assert_eq!(joybus.pak_read(2, REGISTER_C000), ([0x00; 32], 0x00));
+    // Bank 1 is 0x4000 - 0x7fff, where the MBC starts out with ROM bank 1
+    joybus.pak_write(2, REGISTER_A000, [0x01; 32]);
+    assert_eq!(joybus.pak_read(2, REGISTER_C000), ([0x01; 32], 0xeb));
+
+    // Select ROM bank 3 through the MBC at 0x2000, which is 0xe000 in Transfer Pak bank 0
+    joybus.pak_write(2, REGISTER_A000, [0x00; 32]);
+    joybus.pak_write(2, REGISTER_E000, [0x03; 32]);
+    joybus.pak_write(2, REGISTER_A000, [0x01; 32]);
+    assert_eq!(joybus.pak_read(2, REGISTER_C000), ([0x03; 32], 0xb8));
+
+    // MBC1 maps bank 0 to bank 1
+    joybus.pak_write(2, REGISTER_A000, [0x00; 32]);
+    joybus.pak_write(2, REGISTER_E000, [0x00; 32]);
+    joybus.pak_write(2, REGISTER_A000, [0x01; 32]);
+    assert_eq!(joybus.pak_read(2, REGISTER_C000), ([0x01; 32], 0xeb));
+}
+
+#[test]
+fn rejects_short_roms() {
+    let mut joybus = Joybus::plug(2, PakKind::Transfer);
+    // One bank, too short for the bank 1 window
+    joybus.pif.insert_gb_cart(gb_rom(0x4000, 0x01, 0x00, 0x00));
+
+    power_on(&mut joybus);
+    // Cartridge removed
+    assert_eq!(joybus.pak_read(2, REGISTER_B000), ([0xc5; 32], 0xa9));
+    joybus.pak_write(2, REGISTER_A000, [0x01; 32]);
+    assert_eq!(joybus.pak_read(2, REGISTER_C000), ([0x00; 32], 0x00));
+}
+
+#[test]
+fn mbc3_rtc_registers_are_not_save_ram() {
+    let mut joybus = Joybus::plug(2, PakKind::Transfer);
+    // MBC3 with battery backed RAM, 64 KB ROM, 32 KB RAM
+    joybus.pif.insert_gb_cart(gb_rom(0x10000, 0x13, 0x01, 0x03));
+    power_on(&mut joybus);
+
+    // Enable RAM at Game Boy 0x0000, and select RAM bank 0 at 0x4000
+    gb_write(&mut joybus, 0, REGISTER_C000, 0x0a);
+    gb_write(&mut joybus, 1, REGISTER_C000, 0x00);
+    // Save RAM at Game Boy 0xa000, 0xe000 in Transfer Pak bank 2
+    gb_write(&mut joybus, 2, REGISTER_E000, 0x5a);
+    assert_eq!(joybus.pak_read(2, REGISTER_E000), ([0x5a; 32], 0xd1));
+
+    // Select the RTC seconds register instead. It isn't emulated, and mustn't alias RAM bank 0,
+    // for reads or writes.
+    gb_write(&mut joybus, 1, REGISTER_C000, 0x08);
+    joybus.pak_write(2, REGISTER_A000, [0x02; 32]);
+    assert_eq!(joybus.pak_read(2, REGISTER_E000), ([0xff; 32], 0x0a));
+    joybus.pak_write(2, REGISTER_E000, [0x11; 32]);
+
+    gb_write(&mut joybus, 1, REGISTER_C000, 0x00);
+    joybus.pak_write(2, REGISTER_A000, [0x02; 32]);
+    assert_eq!(joybus.pak_read(2, REGISTER_E000), ([0x5a; 32], 0xd1));
+}
@@ -0,0 +1,212 @@
+//! Replay recorded SI traffic into the PIF model, checking it answers the way the recording did
+//!
+//! Recordings are SI captures, raw bus dumps like trace.txt, or transaction listings like
+//! boot_trace.txt. Every request is fed into a `pif_core::Pif`, and wherever the recording has
+//! the PIF's response, the model's has to match it. Side effects on PIF RAM show up in the
+//! responses to later reads, so a bad CIC checksum or joybus response is caught there.
+//!
+//! Recordings of a real PIF include its ROM, which we don't have, so unless an image is given,
+//! the first read of each ROM word before lockout is taken as its contents. Later reads of it,
+//! and reads after lockout, are checked as usual.
+//!
+//! The model's controllers are unplugged, as the recording doesn't say what was plugged in.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use pif_core::cic::Cic;
+use pif_core::pif_ram::PifRam;
+use pif_core::pif_rom::PifRom;
+use pif_core::region::Region;
+use pif_core::{Pif, Request, Response, SiCommand};
+use si_capture::{Reader, Record, Transaction, MAGIC};
+
+const ROM_WORDS: usize = PifRom::SIZE / 4;
+
+/// Read a recording, in whichever of the formats it is
+pub fn load(path: &Path) -> io::Result<Vec<Record>> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(&MAGIC) {
+        return Reader::new(&bytes[..])?.collect();
+    }
+
+    let text = String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if text.lines().any(|line| line.trim_start().starts_with("in:")) {
+        let blocks = raw_trace::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        return Ok(raw_trace::decode(&blocks));
+    }
+    parse_listing(&text)
+}
+
+/// Read a transaction listing. Lines that don't start with a command, like notes and blank
+/// lines, are skipped.
+pub fn parse_listing(text: &str) -> io::Result<Vec<Record>> {
+    const COMMANDS: [&str; 4] = ["Write64", "Read64", "Write4", "Read4"];
+
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if !COMMANDS.iter().any(|command| line.starts_with(command)) {
+            continue;
+        }
+        let transaction: Transaction = line
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: can't parse {line:?}", i + 1)))?;
+        records.push(Record::Transaction(transaction));
+    }
+    Ok(records)
+}
+
+/// A response from the model that doesn't match the recording
+#[derive(Debug)]
+pub struct Mismatch {
+    pub recorded: Transaction,
+    /// None if the recording has the wrong number of words for its command, so it couldn't be
+    /// replayed at all
+    pub model: Option<[u32; 16]>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "recorded: {}", self.recorded)?;
+        write!(f, "model:    ")?;
+        let Some(model) = self.model else {
+            let command = self.recorded.command();
+            return write!(f, "can't replay {} words as a {:?} of {}", self.recorded.len, command, command.words());
+        };
+        for (recorded, model) in self.recorded.data().iter().zip(model) {
+            if *recorded == model {
+                write!(f, " ........")?;
+            } else {
+                write!(f, " {model:08x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Responses compared against the recording
+    pub checked: usize,
+    /// Reads the recording has no data for. They still go to the model, but aren't checked.
+    pub unchecked: usize,
+    /// Writes the recording has no data for, which couldn't be replayed at all
+    pub dropped: usize,
+    /// ROM words taken from the recording
+    pub learned: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for mismatch in &self.mismatches {
+            writeln!(f, "{mismatch}")?;
+        }
+        write!(
+            f,
+            "{} checked, {} mismatched, {} reads without data, {} writes without data dropped, {} ROM words learned",
+            self.checked,
+            self.mismatches.len(),
+            self.unchecked,
+            self.dropped,
+            self.learned
+        )
+    }
+}
+
+pub struct Replay {
+    pif: Pif,
+    region: Region,
+    cic: Cic,
+    /// ROM words we have contents for, from an image or the recording
+    rom_known: [bool; ROM_WORDS],
+    report: Report,
+}
+
+impl Replay {
+    /// The console boots with `cic` in `region`, which is also the region of `rom` if given
+    pub fn new(region: Region, cic: Cic, rom: Option<&[u8]>) -> Self {
+        let mut pif = Pif::new(|| 0);
+        let mut rom_known = [false; ROM_WORDS];
+        if let Some(rom) = rom {
+            let len = rom.len().min(PifRom::SIZE);
+            pif.rom_mut(region).bytes_mut()[..len].copy_from_slice(&rom[..len]);
+            rom_known[..len / 4].fill(true);
+        }
+        pif.boot(region, cic, false);
+        Self { pif, region, cic, rom_known, report: Report::default() }
+    }
+
+    pub fn run<'a>(&mut self, records: impl IntoIterator<Item = &'a Record>) {
+        for record in records {
+            match record {
+                Record::PowerOn(_) => self.pif.boot(self.region, self.cic, false),
+                Record::Transaction(t) => self.transaction(t),
+                Record::Gap(_) => {}
+            }
+        }
+    }
+
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+
+    pub fn into_report(self) -> Report {
+        self.report
+    }
+
+    fn transaction(&mut self, t: &Transaction) {
+        let addr = pif_core::word_addr(t.packet);
+        let recorded = t.data();
+        let command = t.command();
+
+        if recorded.is_empty() {
+            // A write is nothing without its data. A read still happens, in case it matters.
+            if matches!(command, SiCommand::Read4 | SiCommand::Read64) {
+                self.report.unchecked += 1;
+                self.pif.handle(Request::from_packet(t.packet, &[]));
+            } else {
+                self.report.dropped += 1;
+            }
+            return;
+        }
+
+        // A bad recording, which would otherwise be cut short or read past
+        if recorded.len() != command.words() {
+            self.report.mismatches.push(Mismatch { recorded: *t, model: None });
+            return;
+        }
+
+        if command == SiCommand::Read4 && !PifRam::contains(addr) && !self.pif.rom_locked() && !self.rom_known[addr] {
+            let offset = addr * 4;
+            self.pif.rom_mut(self.region).bytes_mut()[offset..offset + 4].copy_from_slice(&recorded[0].to_be_bytes());
+            self.rom_known[addr] = true;
+            self.report.learned += 1;
+        }
+
+        let model = match self.pif.handle(Request::from_packet(t.packet, recorded)) {
+            Response::Read4(word) => {
+                let mut data = [0; 16];
+                data[0] = word;
+                Some(data)
+            }
+            Response::Read64(data) => Some(data),
+            Response::Written => None,
+        };
+
+        if let Some(model) = model {
+            self.report.checked += 1;
+            if model[..recorded.len()] != *recorded {
+                self.report.mismatches.push(Mismatch { recorded: *t, model: Some(model) });
+            }
+        }
+    }
+}
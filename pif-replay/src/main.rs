@@ -0,0 +1,78 @@
+//! Replay a recording into the PIF model and report where it answers differently
+//!
+//!  pif-replay [--region ntsc|pal|mpal] [--cic 6102] [--rom pif_rom.bin] <recording>...
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+
+use pif_core::cic::Cic;
+use pif_core::region::Region;
+use pif_replay::Replay;
+
+fn usage(program: &str) -> ExitCode {
+    eprintln!("usage: {program} [--region ntsc|pal|mpal] [--cic 6102] [--rom pif_rom.bin] <recording>...");
+    ExitCode::FAILURE
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let mut region = Region::Ntsc;
+    let mut cic = Cic::Nus6102;
+    let mut rom = None;
+    let mut recordings = Vec::new();
+
+    let mut i = 1;
+    while i < args.len() {
+        let value = args.get(i + 1).map(String::as_str);
+        match (args[i].as_str(), value) {
+            ("--region", Some(name)) => match Region::from_name(name) {
+                Some(r) => region = r,
+                None => return usage(&args[0]),
+            },
+            ("--cic", Some(number)) => match number.parse().ok().and_then(Cic::from_number) {
+                Some(c) => cic = c,
+                None => return usage(&args[0]),
+            },
+            ("--rom", Some(path)) => match fs::read(path) {
+                Ok(image) => rom = Some(image),
+                Err(e) => {
+                    eprintln!("{path}: {e}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            (arg, _) if arg.starts_with("--") => return usage(&args[0]),
+            (path, _) => {
+                recordings.push(path.to_string());
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+    if recordings.is_empty() {
+        return usage(&args[0]);
+    }
+
+    let mut passed = true;
+    for path in &recordings {
+        let records = match pif_replay::load(Path::new(path)) {
+            Ok(records) => records,
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        let mut replay = Replay::new(region, cic, rom.as_deref());
+        replay.run(&records);
+        println!("{path}: {}", replay.report());
+        passed &= replay.report().passed();
+    }
+
+    if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
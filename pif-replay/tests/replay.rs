@@ -0,0 +1,154 @@
+//! Recorded boots replayed into the PIF model
+
+use std::path::{Path, PathBuf};
+
+use pif_core::cic::Cic;
+use pif_core::region::Region;
+use pif_replay::Replay;
+use si_capture::{Record, SiCommand, Transaction};
+
+fn recording(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(name)
+}
+
+fn transactions(records: &[Record]) -> Vec<Transaction> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            Record::Transaction(t) => Some(*t),
+            _ => None,
+        })
+        .collect()
+}
+
+fn listing(records: &[Record]) -> String {
+    transactions(records).iter().map(|t| format!("{t}\n")).collect()
+}
+
+/// The model's own answers to a boot, kept so changes to it show up. It was never recorded
+/// from a console, so it only catches the model changing, not the model being wrong.
+fn snapshot() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshot_6102.txt")
+}
+
+/// IPL1 fetching itself from a real PIF, as seen by the sniffer
+#[test]
+fn raw_capture_replays() {
+    // The start of IPL1, from its disassembly in picopif's si.rs
+    const IPL1: [u32; 10] = [
+        0x3c09_3400, 0x4089_6000, 0x3c09_0006, 0x3529_e463, 0x4089_8000,
+        0x3c08_a404, 0x8d08_0010, 0x3108_0001, 0x5100_fffd, 0x3c08_a404,
+    ];
+    let rom: Vec<u8> = IPL1.iter().flat_map(|word| word.to_be_bytes()).collect();
+
+    let records = pif_replay::load(&recording("trace.txt")).unwrap();
+    let mut replay = Replay::new(Region::Ntsc, Cic::Nus6102, Some(&rom));
+    replay.run(&records);
+
+    // Every fetch of those words is checked against the disassembly, not the recording
+    let report = replay.into_report();
+    assert!(report.passed(), "{report}");
+    assert_eq!(report.checked, 324);
+    // bfc00024 is in the delay slot of the spin loop's branch likely, which is never fetched
+    let fetched = |addr: u32| transactions(&records).iter().any(|t| t.addr() == addr);
+    assert!((0..0x24).step_by(4).all(fetched));
+    assert!(!fetched(0x24));
+    // IPL1 loops over the same instructions, so plenty of words are read more than once
+    assert!(report.learned < report.checked);
+}
+
+#[test]
+fn snapshot_replays() {
+    let records = pif_replay::load(&snapshot()).unwrap();
+    let mut replay = Replay::new(Region::Ntsc, Cic::Nus6102, None);
+    replay.run(&records);
+
+    let report = replay.into_report();
+    assert!(report.passed(), "{report}");
+    // Every read: the ack, the seed, ROM after lockout, the checksum, cleared RAM, the
+    // terminated boot, and the joybus response
+    assert_eq!(report.checked, 7);
+    assert_eq!((report.unchecked, report.dropped, report.learned), (0, 0, 0));
+}
+
+#[test]
+fn snapshot_catches_wrong_cic() {
+    let records = pif_replay::load(&snapshot()).unwrap();
+    let mut replay = Replay::new(Region::Ntsc, Cic::Nus6103, None);
+    replay.run(&records);
+
+    // The seed, and the Read64 with the checksum
+    let report = replay.into_report();
+    let mismatched: Vec<u32> = report.mismatches.iter().map(|mismatch| mismatch.recorded.clk).collect();
+    assert_eq!(mismatched, [200, 600], "{report}");
+}
+
+#[test]
+fn snapshot_catches_wrong_joybus_response() {
+    let mut records = pif_replay::load(&snapshot()).unwrap();
+    // Say the cartridge answered with an EEPROM
+    let Some(Record::Transaction(response)) = records.last_mut() else { unreachable!() };
+    response.data[6] = 0x0103_00ff;
+
+    let mut replay = Replay::new(Region::Ntsc, Cic::Nus6102, None);
+    replay.run(&records);
+    assert_eq!(replay.report().mismatches.len(), 1);
+}
+
+/// boot_trace.txt was decoded by hand, and only lists the requests
+#[test]
+fn boot_trace_without_data() {
+    let records = pif_replay::load(&recording("boot_trace.txt")).unwrap();
+    let mut replay = Replay::new(Region::Ntsc, Cic::Nus6102, None);
+    replay.run(&records);
+
+    // Nothing can be checked, and the writes can't even be replayed, which the report says
+    let writes = transactions(&records)
+        .iter()
+        .filter(|t| matches!(t.command(), SiCommand::Write4 | SiCommand::Write64))
+        .count();
+    let report = replay.into_report();
+    assert_eq!(report.checked, 0);
+    assert_eq!(report.dropped, writes);
+    assert_eq!(report.unchecked + report.dropped, records.len());
+    assert!(report.to_string().contains(&format!("{writes} writes without data dropped")), "{report}");
+}
+
+#[test]
+fn catches_wrong_response() {
+    let mut records = pif_replay::load(&recording("trace.txt")).unwrap();
+    // The last fetch of the copy loop's first instruction, answered differently. The first
+    // fetch is where its ROM word is learned from, so this one gets checked.
+    let fetch = records.iter_mut().rev().find_map(|record| match record {
+        Record::Transaction(t) if t.addr() == 0xac => Some(t),
+        _ => None,
+    });
+    fetch.expect("no fetch of bfc000ac").data[0] ^= 1;
+
+    let mut replay = Replay::new(Region::Ntsc, Cic::Nus6102, None);
+    replay.run(&records);
+    assert_eq!(replay.report().mismatches.len(), 1);
+}
+
+#[test]
+fn wrong_length_is_a_mismatch() {
+    // A Write64 with one word. Captures and listings refuse to load these, but records can be
+    // made up in memory.
+    let mut t = Transaction::new(0, 0x1f0 << 1, 0);
+    t.len = 1;
+
+    let mut replay = Replay::new(Region::Ntsc, Cic::Nus6102, None);
+    replay.run(&[Record::Transaction(t)]);
+    let report = replay.into_report();
+    assert_eq!(report.mismatches.len(), 1);
+    assert!(report.mismatches[0].model.is_none());
+    assert!(report.to_string().contains("can't replay 1 words as a Write64 of 16"), "{report}");
+}
+
+#[test]
+fn listing_round_trips() {
+    let records = pif_replay::load(&recording("trace.txt")).unwrap();
+    let listing = listing(&records);
+    // The packet's unused low bit isn't in the listing, so compare what is
+    assert_eq!(self::listing(&pif_replay::parse_listing(&listing).unwrap()), listing);
+}
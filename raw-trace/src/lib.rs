@@ -0,0 +1,114 @@
+//! Decode raw SI bus dumps, like trace.txt, into transactions
+//!
+//! A dump is a series of blocks, each a line with the block's offset, then one line per
+//! signal with a bit for every falling edge of the SI clock:
+//!
+//!  540
+//!    in:  000000111111111111111111111111 111111111111111111111111101100 ...
+//!    out: 111111111111111000110101001010 011110010001100011111111111111 ...
+//!    clk: 000000000000000000000000000000 000000000000000000000000000000 ...
+//!
+//! Offsets count bits of the raw sniffer output, which packs `in`, `out` and `clk` side by
+//! side, so a block starts at SI clock `offset / 3`. The dump starts just after the start bit
+//! of the first command, as that's where the sniffer's `read` program starts sampling.
+//!
+//! `clk` is sampled on the falling edges it times, so it's always 0 and tells us nothing. It
+//! is checked for bad bits like the others, then skipped.
+
+use std::fmt;
+
+use si_capture::decode::Decoder;
+use si_capture::Record;
+
+/// Bits of raw sniffer output per SI clock
+const BITS_PER_SAMPLE: u64 = 3;
+
+#[derive(Default)]
+pub struct Block {
+    /// SI clock of the first sample
+    pub clk: u64,
+    pub pif_in: Vec<bool>,
+    pub pif_out: Vec<bool>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn bits(line: usize, text: &str) -> Result<Vec<bool>, ParseError> {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            '0' => Ok(false),
+            '1' => Ok(true),
+            c => Err(ParseError { line, message: format!("unexpected {c:?} in bits") }),
+        })
+        .collect()
+}
+
+/// Split a dump into blocks, checking every block has the same number of samples per signal
+pub fn parse(text: &str) -> Result<Vec<Block>, ParseError> {
+    let mut blocks: Vec<Block> = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if let Ok(offset) = trimmed.parse::<u64>() {
+            blocks.push(Block { clk: offset / BITS_PER_SAMPLE, ..Default::default() });
+            continue;
+        }
+
+        let block = blocks.last_mut().ok_or(ParseError { line: line_no, message: "samples before the first offset".into() })?;
+        let (signal, samples) = trimmed.split_once(':').ok_or(ParseError { line: line_no, message: format!("unexpected line {trimmed:?}") })?;
+        let samples = bits(line_no, samples)?;
+        match signal {
+            "in" => block.pif_in.extend(samples),
+            "out" => block.pif_out.extend(samples),
+            "clk" => {}
+            signal => return Err(ParseError { line: line_no, message: format!("unknown signal {signal:?}") }),
+        }
+    }
+
+    for block in &blocks {
+        if block.pif_in.len() != block.pif_out.len() {
+            return Err(ParseError {
+                line: 0,
+                message: format!("block at clock {} has {} in samples but {} out", block.clk, block.pif_in.len(), block.pif_out.len()),
+            });
+        }
+    }
+    Ok(blocks)
+}
+
+/// Decode the blocks into transactions, timestamped in SI clocks from the start of the dump.
+/// Anything in flight across a gap between blocks is dropped.
+pub fn decode(blocks: &[Block]) -> Vec<Record> {
+    let mut records = vec![Record::PowerOn(0)];
+    let mut decoder = Decoder::after_start_bit();
+
+    for block in blocks {
+        let clk = block.clk as u32;
+        if clk != decoder.clk() {
+            decoder.resync(clk);
+        }
+        for (&pif_in, &pif_out) in block.pif_in.iter().zip(&block.pif_out) {
+            if let Some(transaction) = decoder.sample(pif_in, pif_out) {
+                records.push(Record::Transaction(transaction));
+            }
+        }
+    }
+    records
+}
@@ -0,0 +1,55 @@
+//! Decode a raw SI bus dump, printing every transaction, and optionally saving them as an
+//! SI capture for `si-capture dump`
+//!
+//!  raw-trace <trace.txt> [capture]
+
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::process::ExitCode;
+
+use si_capture::{Header, Mode, PinMap, Record, Writer};
+
+/// How picopif's sniffer is wired up
+const PINS: PinMap = PinMap { clk: 20, pif_in: 18, pif_out: 19, nmi: 21, int2: 22 };
+
+fn run(trace: &str, capture: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let blocks = raw_trace::parse(&fs::read_to_string(trace)?)?;
+    let records = raw_trace::decode(&blocks);
+
+    for record in &records {
+        if let Record::Transaction(t) = record {
+            println!("{t}");
+        }
+    }
+
+    if let Some(path) = capture {
+        // Raw dumps don't say which build made them, or how fast the clock was
+        let header = Header::new(Mode::Listen, PINS, 0, &[]);
+        let mut writer = Writer::new(BufWriter::new(File::create(path)?), &header)?;
+        for record in &records {
+            writer.write(record)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let (trace, capture) = match &args[1..] {
+        [trace] => (trace, None),
+        [trace, capture] => (trace, Some(capture.as_str())),
+        _ => {
+            eprintln!("usage: {} <trace.txt> [capture]", args[0]);
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(trace, capture) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
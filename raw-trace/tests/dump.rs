@@ -0,0 +1,143 @@
+//! Small hand built dumps, parsed and decoded
+
+use si_capture::{Record, SiCommand, Transaction};
+
+/// Samples of the RCP and PIF lines, `in` and `out` in a dump
+type Lines = (Vec<bool>, Vec<bool>);
+
+/// Both lines for a Read4 of `addr` answered with `word`, from the RCP's start bit to a few
+/// idle clocks after the PIF's last data bit
+fn read4(addr: u32, word: u32) -> Lines {
+    let packet = 0xc01 | (addr & 0x7fc) >> 1;
+    let mut rcp = vec![false];
+    rcp.extend((0..12).rev().map(|bit| packet >> bit & 1 != 0));
+
+    let mut pif = vec![true; rcp.len() + 4];
+    pif.push(false);
+    pif.extend((0..32).rev().map(|bit| word >> bit & 1 != 0));
+    pif.extend([true; 4]);
+
+    rcp.resize(pif.len(), true);
+    (rcp, pif)
+}
+
+fn idle(clocks: usize) -> Lines {
+    (vec![true; clocks], vec![true; clocks])
+}
+
+fn concat(parts: &[Lines]) -> Lines {
+    parts.iter().fold((Vec::new(), Vec::new()), |(mut rcp, mut pif), part| {
+        rcp.extend(&part.0);
+        pif.extend(&part.1);
+        (rcp, pif)
+    })
+}
+
+fn line(samples: &[bool]) -> String {
+    let bits: Vec<String> = samples
+        .chunks(30)
+        .map(|chunk| chunk.iter().map(|&bit| if bit { '1' } else { '0' }).collect())
+        .collect();
+    bits.join(" ")
+}
+
+/// A dump with a block at each SI clock, in the same layout as trace.txt
+fn dump(blocks: &[(u64, Lines)]) -> String {
+    blocks
+        .iter()
+        .map(|(clk, (rcp, pif))| {
+            format!(
+                "{}\n  in:  {}\n  out: {}\n  clk: {}\n",
+                clk * 3,
+                line(rcp),
+                line(pif),
+                line(&vec![false; rcp.len()])
+            )
+        })
+        .collect()
+}
+
+fn transaction(clk: u32, addr: u32, word: u32) -> Record {
+    let mut t = Transaction::new(clk, 0xc01 | (addr & 0x7fc) >> 1, 0);
+    t.len = 1;
+    t.data[0] = word;
+    Record::Transaction(t)
+}
+
+fn decode(text: &str) -> Vec<Record> {
+    raw_trace::decode(&raw_trace::parse(text).unwrap())
+}
+
+#[test]
+fn parses_blocks() {
+    let (rcp, pif) = read4(0x7e4, 0x3f3f);
+    let blocks = raw_trace::parse(&dump(&[(0, (rcp.clone(), pif.clone())), (180, idle(7))])).unwrap();
+
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].clk, 0);
+    assert_eq!(blocks[0].pif_in, rcp);
+    assert_eq!(blocks[0].pif_out, pif);
+    assert_eq!(blocks[1].clk, 180);
+    assert_eq!(blocks[1].pif_in.len(), 7);
+}
+
+#[test]
+fn parse_errors() {
+    let error = |text: &str| raw_trace::parse(text).err().expect(text);
+
+    let bad_bit = error("0\n  in:  0110\n  out: 01x0\n");
+    assert_eq!(bad_bit.line, 3);
+    assert!(bad_bit.message.contains("'x'"), "{bad_bit}");
+
+    assert_eq!(error("  in:  0110\n0\n").line, 1);
+    assert_eq!(error("0\n  in:  0110\n  sync: 0000\n").line, 3);
+    assert_eq!(error("0\n  in:  0110\n  what is this\n").line, 3);
+
+    let mismatch = error("0\n  in:  0110\n  out: 011\n");
+    assert!(mismatch.message.contains("4 in samples but 3 out"), "{mismatch}");
+}
+
+#[test]
+fn decodes_from_after_the_first_start_bit() {
+    let (rcp, pif) = concat(&[read4(0x000, 0x3c09_3400), idle(10), read4(0x7e4, 0x3f3f)]);
+    // The sniffer only starts sampling after the first start bit
+    let records = decode(&dump(&[(0, (rcp[1..].to_vec(), pif[1..].to_vec()))]));
+
+    let second = read4(0, 0).0.len() - 1 + 10;
+    assert_eq!(
+        records,
+        [Record::PowerOn(0), transaction(0, 0x000, 0x3c09_3400), transaction(second as u32, 0x7e4, 0x3f3f)]
+    );
+    match records[2] {
+        Record::Transaction(t) => assert_eq!(t.command(), SiCommand::Read4),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn carries_on_across_back_to_back_blocks() {
+    let (rcp, pif) = concat(&[read4(0x000, 0x3c09_3400), read4(0x004, 0x4089_6000)]);
+    let (rcp, pif) = (&rcp[1..], &pif[1..]);
+
+    // Split part way through the second transaction's data. The second block picks up where
+    // the first left off, so nothing is lost.
+    let split = rcp.len() - 10;
+    let text = dump(&[
+        (0, (rcp[..split].to_vec(), pif[..split].to_vec())),
+        (split as u64, (rcp[split..].to_vec(), pif[split..].to_vec())),
+    ]);
+    let second = read4(0, 0).0.len() as u32 - 1;
+    assert_eq!(decode(&text)[1..], [transaction(0, 0x000, 0x3c09_3400), transaction(second, 0x004, 0x4089_6000)]);
+}
+
+#[test]
+fn resyncs_across_gaps() {
+    let (rcp, pif) = concat(&[read4(0x000, 0x3c09_3400), read4(0x004, 0x4089_6000)]);
+    // The second transaction is cut off half way through its data
+    let cut = read4(0, 0).0.len() + 30;
+    let first = (rcp[1..cut].to_vec(), pif[1..cut].to_vec());
+
+    // Samples went missing, and the next block starts later on, with a whole transaction
+    let text = dump(&[(0, first), (1000, concat(&[idle(5), read4(0x008, 0x3c09_0006)]))]);
+    assert_eq!(decode(&text)[1..], [transaction(0, 0x000, 0x3c09_3400), transaction(1005, 0x008, 0x3c09_0006)]);
+}
@@ -0,0 +1,140 @@
+//! Decode SI transactions from the bus, one SI clock at a time
+//!
+//! Both lines idle high. Every packet starts with a low start bit, then one bit per clock:
+//!  1. The RCP sends a 12 bit command on PIF_IN: 2 bits of `SiCommand`, 9 bits of word
+//!     address, and a bit we don't use.
+//!  2. For a read, the PIF answers on PIF_OUT with 32 or 512 bits of data.
+//!     For a write, the PIF sends a lone start bit when it's ready, then the RCP sends 32 or
+//!     512 bits of data on PIF_IN.
+//!
+//! Anything that doesn't finish within `TIMEOUT` clocks is dropped, and we go back to
+//! waiting for a command.
+
+use crate::{SiCommand, Transaction};
+
+const COMMAND_BITS: u16 = 12;
+const TIMEOUT: u32 = 10_000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Line {
+    Rcp,
+    Pif,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Idle,
+    Command,
+    /// Waiting for the PIF to say it's ready for a write
+    Ready { bits: u16 },
+    Start { line: Line, bits: u16 },
+    Data { line: Line, bits: u16 },
+}
+
+pub struct Decoder {
+    state: State,
+    clk: u32,
+    received: u16,
+    transaction: Transaction,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Idle,
+            clk: 0,
+            received: 0,
+            transaction: Transaction::new(0, 0, 0),
+        }
+    }
+
+    /// For a capture that starts just after the start bit of a command
+    pub const fn after_start_bit() -> Self {
+        Self { state: State::Command, ..Self::new() }
+    }
+
+    /// SI clocks seen so far
+    pub fn clk(&self) -> u32 {
+        self.clk
+    }
+
+    /// Skip over clocks where both lines stay high. Only possible between transactions.
+    pub fn skip_idle(&mut self, clocks: u32) -> bool {
+        if matches!(self.state, State::Idle) {
+            self.clk = self.clk.wrapping_add(clocks);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Drop whatever was in flight, after losing samples. Carries on counting from `clk`.
+    pub fn resync(&mut self, clk: u32) {
+        self.clk = clk;
+        self.state = State::Idle;
+    }
+
+    /// Feed in both lines as sampled on one falling edge of the SI clock
+    pub fn sample(&mut self, rcp: bool, pif: bool) -> Option<Transaction> {
+        let clk = self.clk;
+        self.clk = self.clk.wrapping_add(1);
+
+        if !matches!(self.state, State::Idle) && clk.wrapping_sub(self.transaction.clk) > TIMEOUT {
+            self.state = State::Idle;
+        }
+
+        match self.state {
+            State::Idle => {
+                if !rcp {
+                    self.transaction = Transaction::new(clk, 0, 0);
+                    self.received = 0;
+                    self.state = State::Command;
+                }
+            }
+            State::Command => {
+                self.transaction.packet = self.transaction.packet << 1 | rcp as u32;
+                self.received += 1;
+                if self.received == COMMAND_BITS {
+                    self.state = match self.transaction.command() {
+                        SiCommand::Read4 => State::Start { line: Line::Pif, bits: 32 },
+                        SiCommand::Read64 => State::Start { line: Line::Pif, bits: 512 },
+                        SiCommand::Write4 => State::Ready { bits: 32 },
+                        SiCommand::Write64 => State::Ready { bits: 512 },
+                    };
+                }
+            }
+            State::Ready { bits } => {
+                if !pif {
+                    self.state = State::Start { line: Line::Rcp, bits };
+                }
+            }
+            State::Start { line, bits } => {
+                let bit = if line == Line::Rcp { rcp } else { pif };
+                if !bit {
+                    self.received = 0;
+                    self.state = State::Data { line, bits };
+                }
+            }
+            State::Data { line, bits } => {
+                let bit = if line == Line::Rcp { rcp } else { pif };
+                let word = self.transaction.len as usize;
+                self.transaction.data[word] = self.transaction.data[word] << 1 | bit as u32;
+                self.received += 1;
+                if self.received & 31 == 0 {
+                    self.transaction.len += 1;
+                }
+                if self.received == bits {
+                    self.state = State::Idle;
+                    return Some(self.transaction);
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,189 @@
+//! Reading and writing captures to files and sockets
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use crate::{Error, Header, Mode, Record, SiCommand, Transaction, MAX_RECORD_SIZE};
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Truncated => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, format!("{e:?}"))
+    }
+}
+
+pub struct Reader<R> {
+    inner: R,
+    header: Header,
+}
+
+impl<R: Read> Reader<R> {
+    /// Reads the header straight away, so a non-capture is rejected before any records
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut buf = vec![0; 8];
+        inner.read_exact(&mut buf)?;
+        let size = Header::decode_size(buf[..8].try_into().unwrap())?;
+        buf.resize(size.max(8), 0);
+        inner.read_exact(&mut buf[8..])?;
+        let header = Header::decode(&buf)?;
+        Ok(Self { inner, header })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// The next record, or None at a clean end of the capture
+    pub fn read(&mut self) -> io::Result<Option<Record>> {
+        let mut buf = [0; MAX_RECORD_SIZE];
+        match self.inner.read_exact(&mut buf[..1]) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+        // Read up to the transaction length byte, if there is one, then the rest
+        let mut have = 1;
+        let mut size = Record::size(buf[0], 0)?;
+        if size > 5 {
+            self.inner.read_exact(&mut buf[have..size])?;
+            have = size;
+            size = Record::size(buf[0], buf[9])?;
+        }
+        self.inner.read_exact(&mut buf[have..size])?;
+        Ok(Some(Record::decode(&buf[..size])?.0))
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+pub struct Writer<W> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    /// Writes the header straight away
+    pub fn new(mut inner: W, header: &Header) -> io::Result<Self> {
+        let mut buf = [0; crate::MAX_HEADER_SIZE];
+        let len = header.encode(&mut buf);
+        inner.write_all(&buf[..len])?;
+        Ok(Self { inner })
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        let mut buf = [0; MAX_RECORD_SIZE];
+        let len = record.encode(&mut buf);
+        self.inner.write_all(&buf[..len])
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            Mode::Respond => "respond",
+            Mode::Listen => "listen",
+        };
+        writeln!(f, "mode:  {mode}")?;
+        write!(f, "build: ")?;
+        if self.build_id().is_empty() {
+            write!(f, "unknown")?;
+        }
+        for byte in self.build_id() {
+            write!(f, "{byte:02x}")?;
+        }
+        writeln!(f)?;
+        if self.clock_rate == 0 {
+            writeln!(f, "clock: unknown")?;
+        } else {
+            writeln!(f, "clock: {} Hz", self.clock_rate)?;
+        }
+        let p = &self.pins;
+        write!(f, "pins:  clk {}, in {}, out {}, nmi {}, int2 {}", p.clk, p.pif_in, p.pif_out, p.nmi, p.int2)
+    }
+}
+
+/// One line per transaction, in the same style as boot_trace.txt, with the data words after
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {:08x} @ {}", self.command(), 0xbfc0_0000 + self.addr(), self.clk)?;
+        if !self.data().is_empty() {
+            write!(f, ":")?;
+            for word in self.data() {
+                write!(f, " {word:08x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the listing `Display` writes, with or without the data words, so hand written
+/// listings like boot_trace.txt can be read back
+impl FromStr for Transaction {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self, Error> {
+        let (request, data) = line.split_once(':').unwrap_or((line, ""));
+        let mut fields = request.split_whitespace();
+        let command = match fields.next() {
+            Some("Write64") => SiCommand::Write64,
+            Some("Read64") => SiCommand::Read64,
+            Some("Write4") => SiCommand::Write4,
+            Some("Read4") => SiCommand::Read4,
+            _ => return Err(Error::BadListing),
+        };
+        let addr = fields.next().and_then(|addr| u32::from_str_radix(addr, 16).ok()).ok_or(Error::BadListing)?;
+        let clk = match (fields.next(), fields.next(), fields.next()) {
+            (Some("@"), Some(clk), None) => clk.parse().or(Err(Error::BadListing))?,
+            _ => return Err(Error::BadListing),
+        };
+
+        // The packet's unused low bit isn't listed, leave it clear
+        let packet = (command as u32) << 10 | (addr & 0x7fc) >> 1;
+        let mut transaction = Transaction::new(clk, packet, 0);
+        for word in data.split_whitespace() {
+            if transaction.len as usize == command.words() {
+                return Err(Error::BadListing);
+            }
+            transaction.data[transaction.len as usize] = u32::from_str_radix(word, 16).or(Err(Error::BadListing))?;
+            transaction.len += 1;
+        }
+        if transaction.len != 0 && transaction.len as usize != command.words() {
+            return Err(Error::BadListing);
+        }
+        Ok(transaction)
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::PowerOn(clk) => write!(f, "Power on @ {clk}"),
+            Record::Transaction(t) => t.fmt(f),
+            Record::Gap(count) => write!(f, "... {count} transactions lost"),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Binary capture format for SI bus traffic
+//!
+//! A capture is a header, then records until the end of the file or stream. Everything is
+//! little endian.
+//!
+//! Header:
+//!  magic[4] - "SIcp"
+//!  version[2] - `VERSION`
+//!  length[2] - of the whole header, so later versions can add fields on the end
+//!  mode - 0 for picopif answering as the PIF, 1 for passively listening to a real PIF
+//!  pins[5] - GPIO numbers of SI clock, PIF_IN, PIF_OUT, NMI and INT2
+//!  clock_rate[4] - SI clocks per second, 0 if unknown. Timestamps count SI clocks.
+//!  build_id_len, build_id[build_id_len] - GNU build id of the firmware that made the capture
+//!
+//! Records, each a tag byte followed by:
+//!  0x01 clk[4] - the console powered on
+//!  0x02 clk[4], packet[2], wait_count[2], len, data[len * 4] - an SI transaction. For reads
+//!       the data is the PIF's response, for writes it's what the RCP wrote.
+//!  0x03 count[4] - this many transactions were lost
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod decode;
+#[cfg(feature = "std")]
+mod io;
+
+#[cfg(feature = "std")]
+pub use io::{Reader, Writer};
+pub use pif_core::{byte_addr, word_addr, SiCommand};
+
+pub const MAGIC: [u8; 4] = *b"SIcp";
+pub const VERSION: u16 = 1;
+
+const HEADER_FIXED_SIZE: usize = 19;
+pub const MAX_BUILD_ID: usize = 32;
+pub const MAX_HEADER_SIZE: usize = HEADER_FIXED_SIZE + MAX_BUILD_ID;
+pub const MAX_RECORD_SIZE: usize = 10 + 16 * 4;
+
+const TAG_POWER_ON: u8 = 0x01;
+const TAG_TRANSACTION: u8 = 0x02;
+const TAG_GAP: u8 = 0x03;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Not a capture, or a corrupt one
+    BadMagic,
+    /// Made by a newer version of the format
+    UnsupportedVersion(u16),
+    UnknownMode(u8),
+    UnknownTag(u8),
+    /// Ran out of bytes part way through
+    Truncated,
+    /// A transaction with a data length its command doesn't transfer
+    BadLength(u8),
+    /// A line of a transaction listing that doesn't parse
+    BadListing,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Respond = 0,
+    Listen = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PinMap {
+    pub clk: u8,
+    pub pif_in: u8,
+    pub pif_out: u8,
+    pub nmi: u8,
+    pub int2: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub mode: Mode,
+    pub pins: PinMap,
+    pub clock_rate: u32,
+    build_id_len: u8,
+    build_id: [u8; MAX_BUILD_ID],
+}
+
+impl Header {
+    /// Build ids longer than `MAX_BUILD_ID` are cut short
+    pub fn new(mode: Mode, pins: PinMap, clock_rate: u32, build_id: &[u8]) -> Self {
+        let len = build_id.len().min(MAX_BUILD_ID);
+        let mut id = [0; MAX_BUILD_ID];
+        id[..len].copy_from_slice(&build_id[..len]);
+        Self { mode, pins, clock_rate, build_id_len: len as u8, build_id: id }
+    }
+
+    pub fn build_id(&self) -> &[u8] {
+        &self.build_id[..self.build_id_len as usize]
+    }
+
+    pub fn size(&self) -> usize {
+        HEADER_FIXED_SIZE + self.build_id_len as usize
+    }
+
+    /// Returns the number of bytes written. `buf` must hold at least `MAX_HEADER_SIZE`.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        let size = self.size();
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        buf[6..8].copy_from_slice(&(size as u16).to_le_bytes());
+        buf[8] = self.mode as u8;
+        buf[9..14].copy_from_slice(&[self.pins.clk, self.pins.pif_in, self.pins.pif_out, self.pins.nmi, self.pins.int2]);
+        buf[14..18].copy_from_slice(&self.clock_rate.to_le_bytes());
+        buf[18] = self.build_id_len;
+        buf[19..size].copy_from_slice(self.build_id());
+        size
+    }
+
+    /// The length of the whole header, from its first 8 bytes
+    pub fn decode_size(buf: &[u8; 8]) -> Result<usize, Error> {
+        if buf[0..4] != MAGIC {
+            return Err(Error::BadMagic);
+        }
+        let version = u16::from_le_bytes([buf[4], buf[5]]);
+        if version > VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        Ok(u16::from_le_bytes([buf[6], buf[7]]) as usize)
+    }
+
+    /// Decode a whole header, as sized by `decode_size`
+    pub fn decode(buf: &[u8]) -> Result<Self, Error> {
+        let size = Self::decode_size(buf.get(..8).ok_or(Error::Truncated)?.try_into().unwrap())?;
+        if buf.len() < size || size < HEADER_FIXED_SIZE {
+            return Err(Error::Truncated);
+        }
+        let mode = match buf[8] {
+            0 => Mode::Respond,
+            1 => Mode::Listen,
+            mode => return Err(Error::UnknownMode(mode)),
+        };
+        let pins = PinMap { clk: buf[9], pif_in: buf[10], pif_out: buf[11], nmi: buf[12], int2: buf[13] };
+        let clock_rate = u32::from_le_bytes(buf[14..18].try_into().unwrap());
+        let id_len = buf[18] as usize;
+        let build_id = buf.get(19..19 + id_len).ok_or(Error::Truncated)?;
+        Ok(Self::new(mode, pins, clock_rate, build_id))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transaction {
+    /// SI clock count when the command arrived
+    pub clk: u32,
+    /// The 12 bit command packet
+    pub packet: u32,
+    /// How long the responder spun before the packet arrived, 0 when listening
+    pub wait_count: u16,
+    /// Number of valid words in `data`: 0, 1 or 16
+    pub len: u8,
+    pub data: [u32; 16],
+}
+
+impl Transaction {
+    pub const fn new(clk: u32, packet: u32, wait_count: u16) -> Self {
+        Self { clk, packet, wait_count, len: 0, data: [0; 16] }
+    }
+
+    pub fn command(&self) -> SiCommand {
+        SiCommand::from_packet(self.packet)
+    }
+
+    /// PIF byte address
+    pub fn addr(&self) -> u32 {
+        byte_addr(self.packet)
+    }
+
+    pub fn data(&self) -> &[u32] {
+        &self.data[..self.len as usize]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Record {
+    /// The console powered on, at this SI clock count
+    PowerOn(u32),
+    Transaction(Transaction),
+    /// This many transactions were lost
+    Gap(u32),
+}
+
+impl Record {
+    /// Returns the number of bytes written. `buf` must hold at least `MAX_RECORD_SIZE`.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        match self {
+            Record::PowerOn(clk) => {
+                buf[0] = TAG_POWER_ON;
+                buf[1..5].copy_from_slice(&clk.to_le_bytes());
+                5
+            }
+            Record::Transaction(t) => {
+                buf[0] = TAG_TRANSACTION;
+                buf[1..5].copy_from_slice(&t.clk.to_le_bytes());
+                buf[5..7].copy_from_slice(&(t.packet as u16).to_le_bytes());
+                buf[7..9].copy_from_slice(&t.wait_count.to_le_bytes());
+                buf[9] = t.len;
+                for (i, word) in t.data().iter().enumerate() {
+                    buf[10 + i * 4..14 + i * 4].copy_from_slice(&word.to_le_bytes());
+                }
+                10 + t.data().len() * 4
+            }
+            Record::Gap(count) => {
+                buf[0] = TAG_GAP;
+                buf[1..5].copy_from_slice(&count.to_le_bytes());
+                5
+            }
+        }
+    }
+
+    /// Decode one record from the start of `buf`, returning it and its size
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), Error> {
+        let u32_at = |offset: usize| -> Result<u32, Error> {
+            let bytes = buf.get(offset..offset + 4).ok_or(Error::Truncated)?;
+            Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+
+        match *buf.first().ok_or(Error::Truncated)? {
+            TAG_POWER_ON => Ok((Record::PowerOn(u32_at(1)?), 5)),
+            TAG_TRANSACTION => {
+                let fixed = buf.get(..10).ok_or(Error::Truncated)?;
+                let mut t = Transaction::new(
+                    u32_at(1)?,
+                    u16::from_le_bytes([fixed[5], fixed[6]]) as u32,
+                    u16::from_le_bytes([fixed[7], fixed[8]]),
+                );
+                t.len = fixed[9];
+                if t.len != 0 && t.len as usize != t.command().words() {
+                    return Err(Error::BadLength(t.len));
+                }
+                for i in 0..t.len as usize {
+                    t.data[i] = u32_at(10 + i * 4)?;
+                }
+                Ok((Record::Transaction(t), 10 + t.len as usize * 4))
+            }
+            TAG_GAP => Ok((Record::Gap(u32_at(1)?), 5)),
+            tag => Err(Error::UnknownTag(tag)),
+        }
+    }
+
+    /// Total size of the record starting with this tag and, for transactions, this length
+    /// byte. Lets a reader fetch the fixed part of a record, then the rest. A corrupt length
+    /// is an error rather than a guess, which would leave the reader out of step.
+    pub fn size(tag: u8, len: u8) -> Result<usize, Error> {
+        match tag {
+            TAG_POWER_ON | TAG_GAP => Ok(5),
+            TAG_TRANSACTION if len > 16 => Err(Error::BadLength(len)),
+            TAG_TRANSACTION => Ok(10 + len as usize * 4),
+            tag => Err(Error::UnknownTag(tag)),
+        }
+    }
+}
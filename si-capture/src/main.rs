@@ -0,0 +1,70 @@
+//! Pretty-print SI captures, or record one from picopif's trace stream
+//!
+//!  si-capture dump <file>
+//!  si-capture record <host> <file>
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::process::ExitCode;
+
+use si_capture::{Reader, Record, Writer};
+
+/// picopif's trace stream, see trace_stream.rs
+const TRACE_PORT: u16 = 4305;
+
+fn dump(path: &str) -> io::Result<()> {
+    let mut reader = Reader::new(BufReader::new(File::open(path)?))?;
+    let mut out = io::stdout().lock();
+    writeln!(out, "{}", reader.header())?;
+    writeln!(out)?;
+    for record in &mut reader {
+        writeln!(out, "{}", record?)?;
+    }
+    Ok(())
+}
+
+/// Copies records to the file as they arrive, until picopif hangs up or the user hits ^C
+fn record(host: &str, path: &str) -> io::Result<()> {
+    let stream = TcpStream::connect((host, TRACE_PORT))?;
+    let mut reader = Reader::new(BufReader::new(stream))?;
+    eprintln!("{}", reader.header());
+
+    let mut writer = Writer::new(BufWriter::new(File::create(path)?), reader.header())?;
+    let mut transactions = 0u64;
+    while let Some(record) = reader.read()? {
+        match record {
+            Record::Transaction(_) => transactions += 1,
+            Record::PowerOn(_) | Record::Gap(_) => eprintln!("{record}"),
+        }
+        writer.write(&record)?;
+        // Caught up with picopif, keep the file usable if we're interrupted
+        if reader.get_ref().buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+    writer.flush()?;
+    eprintln!("{transactions} transactions");
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().collect();
+    let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[1..] {
+        ["dump", path] => dump(path),
+        ["record", host, path] => record(host, path),
+        _ => {
+            eprintln!("usage: {} dump <file>", args[0]);
+            eprintln!("       {} record <host> <file>", args[0]);
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
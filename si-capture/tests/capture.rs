@@ -0,0 +1,166 @@
+//! Captures encoded and decoded, in memory and through `Reader` and `Writer`
+
+use std::io::{self, Cursor};
+
+use si_capture::{Error, Header, Mode, PinMap, Reader, Record, Transaction, Writer, MAX_HEADER_SIZE, MAX_RECORD_SIZE};
+
+const PINS: PinMap = PinMap { clk: 20, pif_in: 18, pif_out: 19, nmi: 21, int2: 22 };
+
+fn header() -> Header {
+    Header::new(Mode::Listen, PINS, 1_000_000, &[0xde, 0xad, 0xbe, 0xef])
+}
+
+fn read4(clk: u32, word: u32) -> Record {
+    // Read4 of 0x7e4, with the unused low bit set as on the bus
+    let mut t = Transaction::new(clk, 0xc01 | (0x1f9 << 1), 3);
+    t.len = 1;
+    t.data[0] = word;
+    Record::Transaction(t)
+}
+
+fn read64(clk: u32) -> Record {
+    let mut t = Transaction::new(clk, 0x400 | (0x1f0 << 1), 0);
+    t.len = 16;
+    for (i, word) in t.data.iter_mut().enumerate() {
+        *word = 0x0101_0101 * i as u32;
+    }
+    Record::Transaction(t)
+}
+
+fn encode(record: &Record) -> Vec<u8> {
+    let mut buf = [0; MAX_RECORD_SIZE];
+    let len = record.encode(&mut buf);
+    buf[..len].to_vec()
+}
+
+#[test]
+fn header_round_trips() {
+    let header = header();
+    let mut buf = [0; MAX_HEADER_SIZE];
+    let len = header.encode(&mut buf);
+
+    assert_eq!(len, header.size());
+    assert_eq!(buf[..8], [b'S', b'I', b'c', b'p', 1, 0, len as u8, 0]);
+    assert_eq!(Header::decode_size(buf[..8].try_into().unwrap()), Ok(len));
+    assert_eq!(Header::decode(&buf[..len]), Ok(header));
+    assert_eq!(header.build_id(), [0xde, 0xad, 0xbe, 0xef]);
+}
+
+#[test]
+fn long_build_ids_are_cut_short() {
+    let header = Header::new(Mode::Respond, PINS, 0, &[0x55; 40]);
+    assert_eq!(header.build_id(), [0x55; 32]);
+    assert_eq!(header.size(), MAX_HEADER_SIZE);
+}
+
+#[test]
+fn header_errors() {
+    let mut buf = [0; MAX_HEADER_SIZE];
+    let len = header().encode(&mut buf);
+
+    let mut bad = buf;
+    bad[0] = b's';
+    assert_eq!(Header::decode(&bad[..len]), Err(Error::BadMagic));
+
+    let mut newer = buf;
+    newer[4] = 2;
+    assert_eq!(Header::decode(&newer[..len]), Err(Error::UnsupportedVersion(2)));
+
+    let mut mode = buf;
+    mode[8] = 7;
+    assert_eq!(Header::decode(&mode[..len]), Err(Error::UnknownMode(7)));
+
+    assert_eq!(Header::decode(&buf[..5]), Err(Error::Truncated));
+    assert_eq!(Header::decode(&buf[..len - 1]), Err(Error::Truncated));
+}
+
+#[test]
+fn newer_headers_can_grow() {
+    // A later version of the format with two more bytes on the end of the header
+    let mut buf = [0; MAX_HEADER_SIZE + 2];
+    let len = header().encode(&mut buf) + 2;
+    buf[6] = len as u8;
+    assert_eq!(Header::decode(&buf[..len]), Ok(header()));
+}
+
+#[test]
+fn records_round_trip() {
+    let records = [Record::PowerOn(1234), read4(1892, 0x3f3f), read64(20973), Record::Gap(7)];
+    let sizes = [5, 14, 74, 5];
+
+    for (record, size) in records.iter().zip(sizes) {
+        let bytes = encode(record);
+        assert_eq!(bytes.len(), size);
+        assert_eq!(Record::size(bytes[0], bytes.get(9).copied().unwrap_or(0)), Ok(size));
+        assert_eq!(Record::decode(&bytes), Ok((*record, size)));
+    }
+
+    // Little endian clock, packet and wait count
+    assert_eq!(encode(&read4(0x0102_0304, 0))[..10], [0x02, 0x04, 0x03, 0x02, 0x01, 0xf3, 0x0f, 0x03, 0x00, 0x01]);
+}
+
+#[test]
+fn record_errors() {
+    assert_eq!(Record::decode(&[]), Err(Error::Truncated));
+    assert_eq!(Record::decode(&[0x09, 0, 0, 0, 0]), Err(Error::UnknownTag(0x09)));
+    assert_eq!(Record::size(0x09, 0), Err(Error::UnknownTag(0x09)));
+
+    let bytes = encode(&read64(0));
+    assert_eq!(Record::decode(&bytes[..bytes.len() - 1]), Err(Error::Truncated));
+    assert_eq!(Record::decode(&bytes[..9]), Err(Error::Truncated));
+
+    // A length that can't be right is an error, not something to make the best of
+    let mut long = bytes.clone();
+    long[9] = 17;
+    assert_eq!(Record::size(long[0], long[9]), Err(Error::BadLength(17)));
+    assert_eq!(Record::decode(&long), Err(Error::BadLength(17)));
+    let mut short = bytes;
+    short[9] = 1;
+    assert_eq!(Record::decode(&short), Err(Error::BadLength(1)));
+}
+
+#[test]
+fn transactions_without_data() {
+    // A packet the responder couldn't answer is recorded without any
+    let record = Record::Transaction(Transaction::new(10, 0, 0));
+    let bytes = encode(&record);
+    assert_eq!(bytes.len(), 10);
+    assert_eq!(Record::decode(&bytes), Ok((record, 10)));
+}
+
+#[test]
+fn reader_reads_what_writer_wrote() {
+    let records = [Record::PowerOn(0), read4(1892, 0x3f3f), read64(20973), Record::Gap(2), read4(30000, 0)];
+
+    let mut writer = Writer::new(Vec::new(), &header()).unwrap();
+    for record in &records {
+        writer.write(record).unwrap();
+    }
+    let bytes = writer.into_inner();
+
+    let mut reader = Reader::new(Cursor::new(&bytes)).unwrap();
+    assert_eq!(*reader.header(), header());
+    let read: Vec<Record> = reader.by_ref().collect::<io::Result<_>>().unwrap();
+    assert_eq!(read, records);
+    assert!(reader.read().unwrap().is_none());
+}
+
+#[test]
+fn reader_errors() {
+    let mut writer = Writer::new(Vec::new(), &header()).unwrap();
+    writer.write(&read64(0)).unwrap();
+    let bytes = writer.into_inner();
+
+    let not_a_capture = Reader::new(Cursor::new(b"Read4 bfc00000 @ 1892\n")).err().unwrap();
+    assert_eq!(not_a_capture.kind(), io::ErrorKind::InvalidData);
+
+    // Cut off part way through a record
+    let mut reader = Reader::new(Cursor::new(&bytes[..bytes.len() - 3])).unwrap();
+    assert_eq!(reader.read().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+    // A corrupt length stops the reader, instead of it carrying on from the wrong place
+    let mut corrupt = bytes.clone();
+    corrupt[header().size() + 9] = 0xff;
+    let mut reader = Reader::new(Cursor::new(&corrupt)).unwrap();
+    assert_eq!(reader.read().unwrap_err().kind(), io::ErrorKind::InvalidData);
+}